@@ -17,6 +17,18 @@ pub enum Error {
     /// Procfs files have unexpected format
     #[error("Unexpected format in {path}")]
     ProcFsFormatError { path: std::path::PathBuf },
+    /// A [RegionProvider](crate::RegionProvider) failed to supply its regions
+    #[error("Region provider failed: {source}")]
+    RegionProviderError {
+        #[source]
+        source: crate::ProviderError,
+    },
+    /// The background thread for the checker couldn't be started
+    #[error("Unable to spawn checker thread: {source}")]
+    ThreadSpawnError {
+        #[source]
+        source: std::io::Error,
+    },
 }
 
 #[cfg(windows)]
@@ -28,3 +40,13 @@ pub(crate) fn win_get_last_error(syscall: &str) -> Error {
         message: error.message().to_string_lossy(),
     }
 }
+
+#[cfg(unix)]
+pub(crate) fn unix_get_last_error(syscall: &str) -> Error {
+    let error = std::io::Error::last_os_error();
+    Error::SysCallError {
+        syscall: syscall.into(),
+        code: error.raw_os_error().unwrap_or_default(),
+        message: error.to_string(),
+    }
+}
@@ -0,0 +1,90 @@
+use std::borrow::Cow;
+
+/// A private copy of the contents of a region, to find out exactly which bytes changed
+#[derive(Debug)]
+pub(crate) enum GoldenCopy {
+    Raw(Box<[u8]>),
+    #[cfg(feature = "compression")]
+    Compressed(Vec<u8>),
+}
+
+impl GoldenCopy {
+    #[cfg_attr(not(feature = "compression"), allow(unused_variables))]
+    pub(crate) fn new(bytes: &[u8], compress: bool) -> Self {
+        #[cfg(feature = "compression")]
+        if compress {
+            return Self::Compressed(miniz_oxide::deflate::compress_to_vec(bytes, 1));
+        }
+        Self::Raw(bytes.into())
+    }
+
+    pub(crate) fn bytes(&self) -> Cow<'_, [u8]> {
+        match self {
+            Self::Raw(bytes) => Cow::Borrowed(bytes),
+            #[cfg(feature = "compression")]
+            Self::Compressed(compressed) => Cow::Owned(
+                miniz_oxide::inflate::decompress_to_vec(compressed)
+                    .expect("golden copy was compressed by us"),
+            ),
+        }
+    }
+}
+
+/// A single byte that differs from the golden copy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ByteDiff {
+    /// offset from the start of the region
+    pub offset: usize,
+    /// value in the golden copy
+    pub old: u8,
+    /// value found in memory
+    pub new: u8,
+}
+
+impl ByteDiff {
+    /// how many bits differ between old and new
+    pub fn flipped_bits(&self) -> u32 {
+        (self.old ^ self.new).count_ones()
+    }
+}
+
+/// all bytes that differ between old and new
+pub(crate) fn byte_diffs(old: &[u8], new: &[u8]) -> Vec<ByteDiff> {
+    old.iter()
+        .zip(new)
+        .enumerate()
+        .filter(|(_, (old, new))| old != new)
+        .map(|(offset, (&old, &new))| ByteDiff { offset, old, new })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_flipped_bits() {
+        let old = [0u8, 1, 2, 3, 4, 5];
+        let new = [0u8, 1, 6, 3, 4, 0xfa];
+        for compress in [false, true] {
+            let golden = GoldenCopy::new(&old, compress);
+            let diffs = byte_diffs(&golden.bytes(), &new);
+            assert_eq!(
+                diffs,
+                vec![
+                    ByteDiff {
+                        offset: 2,
+                        old: 2,
+                        new: 6
+                    },
+                    ByteDiff {
+                        offset: 5,
+                        old: 5,
+                        new: 0xfa
+                    }
+                ]
+            );
+            assert_eq!(diffs.iter().map(ByteDiff::flipped_bits).sum::<u32>(), 1 + 8);
+        }
+    }
+}
@@ -0,0 +1,585 @@
+use std::{
+    any::Any,
+    collections::HashMap,
+    ffi::OsStr,
+    path::Path,
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc, Condvar, Mutex,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    error::Error, CheckerConfig, CheckerEvent, Permissions, Region, Segment, Snapshot, VerifyReport,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ControlState {
+    Running,
+    Paused,
+    Stopped,
+}
+
+/// State shared between a [CheckerHandle] and the checker thread it controls
+#[derive(Debug)]
+pub(crate) struct Control {
+    state: Mutex<ControlState>,
+    wakeup: Condvar,
+    subscribers: Mutex<Vec<Sender<CheckerEvent>>>,
+    baseline: Mutex<Baseline>,
+    /// notified when the checker handed over a baseline, or exited
+    baseline_answered: Condvar,
+    registered: Mutex<Registered>,
+    modules: Mutex<Modules>,
+    /// notified when the checker stops reading a module
+    module_released: Condvar,
+}
+
+/// Hashes as of the last completed round, see [CheckerHandle::snapshot]. The checker only copies its hashes when they
+/// are asked for, instead of after every round
+#[derive(Debug)]
+struct Baseline {
+    /// the last baseline the checker handed over
+    snapshot: Snapshot,
+    /// number of baselines asked for
+    requested: u64,
+    /// number of requests answered by the last handover
+    answered: u64,
+    /// the checker exited and won't answer anymore
+    finished: bool,
+}
+
+/// Memory registered with [CheckerHandle::register_region]
+#[derive(Debug, Default)]
+struct Registered {
+    next_id: u64,
+    regions: HashMap<RegionId, (Region, Owner)>,
+}
+
+/// Modules that are being unloaded or read, see [CheckerHandle::unload_guard]
+#[derive(Debug, Default)]
+struct Modules {
+    /// paths passed to [CheckerHandle::unload_guard], one entry per guard
+    unloading: Vec<String>,
+    /// paths of dropped guards, with the number of guards dropped before. Regions found before the guard was dropped
+    /// may belong to the unloaded module, so they stay unread until regions are found anew
+    unloaded: Vec<(u64, String)>,
+    /// number of guards dropped so far
+    unload_count: u64,
+    /// sources of the regions the checker is reading right now, one entry per reader
+    in_use: Vec<String>,
+}
+
+/// true if a region's source refers to a module path passed to [CheckerHandle::unload_guard]. A bare file name like
+/// `libplugin.so`, as it is passed to `dlopen`, matches that file in any directory
+fn is_module(source: &str, path: &str) -> bool {
+    source == path
+        || (Path::new(path).components().count() == 1
+            && Path::new(source).file_name() == Some(OsStr::new(path)))
+}
+
+/// Marks a module as being read by the checker, so it isn't unloaded meanwhile
+pub(crate) struct ModuleInUse<'a> {
+    control: &'a Control,
+    source: Option<String>,
+}
+
+impl Drop for ModuleInUse<'_> {
+    fn drop(&mut self) {
+        let Some(source) = &self.source else {
+            return;
+        };
+        let mut modules = self.control.modules.lock().unwrap();
+        if let Some(index) = modules.in_use.iter().position(|used| used == source) {
+            modules.in_use.swap_remove(index);
+        }
+        self.control.module_released.notify_all();
+    }
+}
+
+/// keeps registered memory alive
+type Owner = Arc<dyn Any + Send + Sync>;
+
+/// Identifies a region registered with [CheckerHandle::register_region]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RegionId(u64);
+
+impl Control {
+    pub(crate) fn new(config: &CheckerConfig) -> Self {
+        Self {
+            state: Mutex::new(ControlState::Running),
+            wakeup: Condvar::new(),
+            subscribers: Mutex::new(vec![]),
+            baseline: Mutex::new(Baseline {
+                snapshot: Snapshot::new(config, Default::default()),
+                requested: 0,
+                answered: 0,
+                finished: false,
+            }),
+            baseline_answered: Condvar::new(),
+            registered: Default::default(),
+            modules: Default::default(),
+            module_released: Condvar::new(),
+        }
+    }
+
+    /// mark the module a region belongs to as in use until the returned value is dropped. None if the module is
+    /// being unloaded, or was unloaded since the region was found, in which case the region must not be read
+    pub(crate) fn use_module(&self, region: &Region) -> Option<ModuleInUse<'_>> {
+        // only file backed memory is unloaded
+        if region.file.is_none() {
+            return Some(ModuleInUse {
+                control: self,
+                source: None,
+            });
+        }
+        let mut modules = self.modules.lock().unwrap();
+        if modules
+            .unloading
+            .iter()
+            .chain(modules.unloaded.iter().map(|(_, path)| path))
+            .any(|path| is_module(&region.source, path))
+        {
+            return None;
+        }
+        modules.in_use.push(region.source.clone());
+        Some(ModuleInUse {
+            control: self,
+            source: Some(region.source.clone()),
+        })
+    }
+
+    /// number of unload guards dropped so far, to pass to [forget_unloaded](Self::forget_unloaded) after the regions
+    /// were found anew
+    pub(crate) fn unload_count(&self) -> u64 {
+        self.modules.lock().unwrap().unload_count
+    }
+
+    /// allow reading modules again whose guard was dropped before `unload_count` was taken. Regions found since then
+    /// don't belong to them anymore
+    pub(crate) fn forget_unloaded(&self, unload_count: u64) {
+        let mut modules = self.modules.lock().unwrap();
+        modules.unloaded.retain(|(count, _)| *count >= unload_count);
+    }
+
+    /// all registered regions, with their owners to keep them alive while they are hashed
+    pub(crate) fn registered_regions(&self) -> Vec<(Region, Owner)> {
+        self.registered
+            .lock()
+            .unwrap()
+            .regions
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    /// hand over the checker's hashes if they were asked for since the last handover
+    pub(crate) fn answer_baseline(&self, snapshot: impl FnOnce() -> Snapshot) {
+        let mut baseline = self.baseline.lock().unwrap();
+        if baseline.answered < baseline.requested {
+            baseline.snapshot = snapshot();
+            baseline.answered = baseline.requested;
+            self.baseline_answered.notify_all();
+        }
+    }
+
+    fn baseline_requested(&self) -> bool {
+        let baseline = self.baseline.lock().unwrap();
+        baseline.answered < baseline.requested
+    }
+
+    /// marks the checker as finished when dropped, so nobody waits for it to hand over a baseline anymore
+    pub(crate) fn finish_on_drop(&self) -> FinishOnDrop<'_> {
+        FinishOnDrop(self)
+    }
+
+    /// send an event to all subscribers
+    pub(crate) fn publish(&self, event: CheckerEvent) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        // drop subscribers whose receiver is gone
+        subscribers.retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+
+    fn set(&self, new_state: ControlState) {
+        let mut state = self.state.lock().unwrap();
+        // a stopped checker stays stopped
+        if *state != ControlState::Stopped {
+            *state = new_state;
+        }
+        self.wakeup.notify_all();
+    }
+
+    /// interrupt [wait](Self::wait) to let the checker look for requests
+    fn wake(&self) {
+        let _state = self.state.lock().unwrap();
+        self.wakeup.notify_all();
+    }
+
+    fn get(&self) -> ControlState {
+        *self.state.lock().unwrap()
+    }
+
+    /// sleep for `duration`, or until the checker is stopped. While paused this blocks until resumed. Requests for
+    /// the baseline are answered with `answer` meanwhile.
+    ///
+    /// returns false if the checker should stop
+    pub(crate) fn wait(&self, duration: Duration, mut answer: impl FnMut()) -> bool {
+        let deadline = Instant::now() + duration;
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if self.baseline_requested() {
+                drop(state);
+                answer();
+                state = self.state.lock().unwrap();
+                continue;
+            }
+            match *state {
+                ControlState::Stopped => return false,
+                ControlState::Paused => state = self.wakeup.wait(state).unwrap(),
+                ControlState::Running => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return true;
+                    }
+                    state = self.wakeup.wait_timeout(state, deadline - now).unwrap().0;
+                }
+            }
+        }
+    }
+}
+
+/// Returned by [Control::finish_on_drop]
+pub(crate) struct FinishOnDrop<'a>(&'a Control);
+
+impl Drop for FinishOnDrop<'_> {
+    fn drop(&mut self) {
+        self.0.baseline.lock().unwrap().finished = true;
+        self.0.baseline_answered.notify_all();
+    }
+}
+
+/// Handle to a checker running on a background thread, returned by [spawn()](crate::ProcessConsistencyChecker::spawn)
+///
+/// Dropping the handle detaches the checker thread, same as dropping a [JoinHandle]
+#[derive(Debug)]
+pub struct CheckerHandle {
+    control: Arc<Control>,
+    thread: JoinHandle<Result<(), Error>>,
+}
+
+impl CheckerHandle {
+    pub(crate) fn new(control: Arc<Control>, thread: JoinHandle<Result<(), Error>>) -> Self {
+        Self { control, thread }
+    }
+
+    /// signal the checker to stop. A check round that is currently running is finished first,
+    /// a sleep between rounds is interrupted immediately. Use [join()](Self::join) to wait for the thread to exit
+    pub fn stop(&self) {
+        self.control.set(ControlState::Stopped);
+    }
+
+    /// wait for the checker thread to exit. Returns the error that terminated the checker, if any.
+    ///
+    /// Panics from the error callback are propagated to the caller
+    pub fn join(self) -> Result<(), Error> {
+        match self.thread.join() {
+            Ok(result) => result,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+
+    /// stop and wait for the checker thread to exit, see [stop()](Self::stop) and [join()](Self::join)
+    pub fn stop_and_join(self) -> Result<(), Error> {
+        self.stop();
+        self.join()
+    }
+
+    /// suspend checks after the current round, until [resume()](Self::resume) is called
+    pub fn pause(&self) {
+        self.control.set(ControlState::Paused);
+    }
+
+    /// continue checks suspended by [pause()](Self::pause)
+    pub fn resume(&self) {
+        self.control.set(ControlState::Running);
+    }
+
+    /// receive all [CheckerEvent]s from now on through a channel, in addition to the event callback.
+    ///
+    /// This allows handling errors on another thread, without doing any work in the checker's hashing loop.
+    /// Can be called multiple times, every receiver gets every event
+    ///
+    /// ```rust
+    ///   use process_consistency::{CheckerEvent, ProcessConsistencyChecker};
+    ///   let handle = ProcessConsistencyChecker::new().spawn(|_| {}).unwrap();
+    ///   let events = handle.subscribe();
+    ///   std::thread::spawn(move || {
+    ///     for event in events {
+    ///       if let CheckerEvent::HashMismatch(error) = event {
+    ///         eprintln!("Memory Error: {:#?}", &error);
+    ///       }
+    ///     }
+    ///   });
+    /// ```
+    pub fn subscribe(&self) -> Receiver<CheckerEvent> {
+        let (sender, receiver) = channel();
+        self.control.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    /// the hashes of all regions as of the last completed round. If a round is running, this waits until it
+    /// completed. Empty if no round completed yet.
+    ///
+    /// The checker only copies its hashes when they are asked for. Called from the event callback, this returns the
+    /// hashes that were handed over last instead, because the checker can't answer while it runs the callback
+    pub fn snapshot(&self) -> Snapshot {
+        if std::thread::current().id() == self.thread.thread().id() {
+            return self.control.baseline.lock().unwrap().snapshot.clone();
+        }
+        let request = {
+            let mut baseline = self.control.baseline.lock().unwrap();
+            baseline.requested += 1;
+            baseline.requested
+        };
+        // wake the checker if it's sleeping between rounds
+        self.control.wake();
+        let mut baseline = self.control.baseline.lock().unwrap();
+        while baseline.answered < request && !baseline.finished {
+            baseline = self.control.baseline_answered.wait(baseline).unwrap();
+        }
+        baseline.snapshot.clone()
+    }
+
+    /// hash all regions right now and compare them to the hashes known to the background checker, instead of
+    /// waiting for the next round.
+    ///
+    /// The hashes are taken from [snapshot](Self::snapshot), so a round that is running is finished first. Regions the
+    /// checker hasn't hashed yet are reported as [added](VerifyReport::added), so before the first round finished the
+    /// report contains no changes. Modules that are being [unloaded](Self::unload_guard)
+    /// aren't read, and are reported as [removed](VerifyReport::removed)
+    pub fn verify_now(&self) -> Result<VerifyReport, Error> {
+        let registered = self.control.registered_regions();
+        self.snapshot().verify_with(
+            registered
+                .iter()
+                .map(|(region, _)| region.clone())
+                .collect(),
+            Some(&self.control),
+        )
+    }
+
+    /// check memory that isn't code, e.g. large immutable buffers like lookup tables or model weights, on the same
+    /// schedule as code. The memory is owned by the checker until it is [unregistered](Self::unregister_region).
+    ///
+    /// The region is reported with `label` as [source](Region::source) and [Segment::Custom], in the same events as
+    /// any other region, starting with the next round. It is never [repaired](crate::ProcessConsistencyChecker::repair)
+    ///
+    /// ```rust
+    ///   use std::sync::Arc;
+    ///   use process_consistency::ProcessConsistencyChecker;
+    ///   let handle = ProcessConsistencyChecker::new().skip_libs(true).spawn(|_| {}).unwrap();
+    ///   let table: Arc<[u8]> = vec![1, 2, 3].into();
+    ///   let id = handle.register_region("lookup table", table.clone());
+    ///   // ...
+    ///   handle.unregister_region(id);
+    /// ```
+    pub fn register_region(
+        &self,
+        label: impl Into<String>,
+        data: impl AsRef<[u8]> + Send + Sync + 'static,
+    ) -> RegionId {
+        // move data to its final place first, inline data like arrays would move with it otherwise
+        let data = Arc::new(data);
+        let bytes = (*data).as_ref();
+        let (start, len) = (bytes.as_ptr(), bytes.len());
+        // SAFETY: data is kept alive as the owner, and can't be moved or mutated because it's only accessed through
+        // a shared reference from now on
+        unsafe { self.insert_region(label, start, len, data) }
+    }
+
+    /// like [register_region](Self::register_region), for memory that is owned by something else than a byte
+    /// buffer, e.g. a memory mapped file. `owner` is kept alive until the region is unregistered
+    ///
+    /// # SAFETY
+    /// `start..start + len` has to stay readable and unchanged as long as `owner` is alive, apart from the changes
+    /// the checker is supposed to find
+    pub unsafe fn register_raw_region(
+        &self,
+        label: impl Into<String>,
+        start: *const u8,
+        len: usize,
+        owner: impl Send + Sync + 'static,
+    ) -> RegionId {
+        unsafe { self.insert_region(label, start, len, Arc::new(owner)) }
+    }
+
+    /// # SAFETY
+    /// same as [register_raw_region](Self::register_raw_region)
+    unsafe fn insert_region(
+        &self,
+        label: impl Into<String>,
+        start: *const u8,
+        len: usize,
+        owner: Owner,
+    ) -> RegionId {
+        let region = Region {
+            start: start as usize,
+            end: start as usize + len,
+            source: label.into(),
+            permissions: Permissions {
+                read: true,
+                ..Default::default()
+            },
+            segment: Segment::Custom,
+            file: None,
+        };
+        let mut registered = self.control.registered.lock().unwrap();
+        let id = RegionId(registered.next_id);
+        registered.next_id += 1;
+        registered.regions.insert(id, (region, owner));
+        id
+    }
+
+    /// stop checking a region registered with [register_region](Self::register_region). Its owner is dropped as
+    /// soon as the checker doesn't use it anymore. Returns false if the region wasn't registered
+    pub fn unregister_region(&self, id: RegionId) -> bool {
+        let removed = self.control.registered.lock().unwrap().regions.remove(&id);
+        removed.is_some()
+    }
+
+    /// announce that a module is about to be unloaded, e.g. with `dlclose`. Blocks until the checker finished
+    /// reading the module, and keeps the checker away from it until the returned guard is dropped, and from then on
+    /// until the checker found its regions anew. Its regions are dropped from the checker and reported as
+    /// [RegionRemoved](CheckerEvent::RegionRemoved).
+    ///
+    /// `path` is the path of the module, or a bare file name to match that file in any directory. This makes it safe
+    /// to check shared libraries in a process that unloads them. Must not be called from the event callback, which
+    /// runs while the module is in use.
+    ///
+    /// ```rust
+    ///   use process_consistency::ProcessConsistencyChecker;
+    ///   let handle = ProcessConsistencyChecker::new().spawn(|_| {}).unwrap();
+    ///   let guard = handle.unload_guard("libplugin.so");
+    ///   // unsafe { libc::dlclose(plugin) };
+    ///   drop(guard);
+    /// ```
+    ///
+    /// With [search_once](crate::ProcessConsistencyChecker::search_once), a module that stays loaded after all is
+    /// only checked again after the next rescan
+    pub fn unload_guard(&self, path: impl AsRef<Path>) -> UnloadGuard {
+        let path = path.as_ref();
+        let resolved = std::fs::canonicalize(path).ok();
+        // a name like `libplugin.so.1` is usually a symlink to the file that is actually mapped
+        #[cfg(unix)]
+        let resolved = resolved.or_else(|| crate::linux::loaded_object_path(path.as_os_str()));
+        let path = resolved
+            .unwrap_or_else(|| path.to_owned())
+            .to_string_lossy()
+            .into_owned();
+        let mut modules = self.control.modules.lock().unwrap();
+        modules.unloading.push(path.clone());
+        while modules.in_use.iter().any(|source| is_module(source, &path)) {
+            modules = self.control.module_released.wait(modules).unwrap();
+        }
+        UnloadGuard {
+            control: self.control.clone(),
+            path,
+        }
+    }
+
+    /// true if the checker is paused
+    pub fn is_paused(&self) -> bool {
+        self.control.get() == ControlState::Paused
+    }
+
+    /// true while the checker thread is alive, i.e. it hasn't been stopped and hasn't encountered an error
+    pub fn is_running(&self) -> bool {
+        !self.thread.is_finished()
+    }
+}
+
+/// Keeps the checker away from a module that is being unloaded, returned by [CheckerHandle::unload_guard]
+#[derive(Debug)]
+pub struct UnloadGuard {
+    control: Arc<Control>,
+    path: String,
+}
+
+impl Drop for UnloadGuard {
+    fn drop(&mut self) {
+        let mut modules = self.control.modules.lock().unwrap();
+        if let Some(index) = modules.unloading.iter().position(|path| *path == self.path) {
+            modules.unloading.swap_remove(index);
+        }
+        // the checker may still hold regions of the module it found before it was unloaded
+        let count = modules.unload_count;
+        modules.unloaded.push((count, self.path.clone()));
+        modules.unload_count += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ProcessConsistencyChecker;
+
+    #[test]
+    fn registered_arrays_are_checked_where_they_are_owned() {
+        let handle = ProcessConsistencyChecker::new()
+            .skip_libs(true)
+            .spawn(|_| {})
+            .unwrap();
+        handle.register_region("array", [7u8; 256]);
+        let registered = handle.control.registered_regions();
+        let (region, owner) = &registered[0];
+        let array = owner.downcast_ref::<[u8; 256]>().unwrap();
+        assert_eq!(region.start, array.as_ptr() as usize);
+        assert_eq!(region.end - region.start, 256);
+        handle.stop_and_join().unwrap();
+    }
+
+    #[test]
+    fn verify_now_skips_modules_being_unloaded() {
+        let _lock = crate::tests::CODE_LOCK.lock().unwrap();
+        let handle = ProcessConsistencyChecker::new()
+            .skip_libs(true)
+            .spawn(|_| {})
+            .unwrap();
+        // wait for the first round
+        while handle.snapshot().is_empty() {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+
+        let program = std::env::current_exe().unwrap();
+        let program = program.to_str().unwrap();
+        let guard = handle.unload_guard(program);
+        let report = handle.verify_now().unwrap();
+        assert!(report.is_intact());
+        assert!(report
+            .unchanged
+            .iter()
+            .all(|region| region.source != program));
+        assert!(report.removed.iter().any(|region| region.source == program));
+        drop(guard);
+        handle.stop_and_join().unwrap();
+    }
+
+    #[test]
+    fn snapshot_is_handed_over_on_request() {
+        let handle = ProcessConsistencyChecker::new()
+            .skip_libs(true)
+            .check_period(std::time::Duration::from_secs(3600))
+            .spawn(|_| {})
+            .unwrap();
+        // answered right away while the checker sleeps, once the first round completed
+        while handle.snapshot().is_empty() {}
+        handle.pause();
+        assert!(!handle.snapshot().is_empty());
+        let control = handle.control.clone();
+        handle.stop_and_join().unwrap();
+        let baseline = control.baseline.lock().unwrap();
+        assert!(baseline.finished);
+        assert!(!baseline.snapshot.is_empty());
+    }
+}
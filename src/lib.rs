@@ -6,14 +6,19 @@
 //! # Basic Usage
 //!
 //! ```rust
-//!   use process_consistency::ProcessConsistencyChecker;
+//!   use process_consistency::{CheckerEvent, ProcessConsistencyChecker};
 //!   std::thread::spawn(|| {
-//!     ProcessConsistencyChecker::new().run(|error| { panic!("Memory Error: {:#?}", &error) }).unwrap()
+//!     ProcessConsistencyChecker::new().run(|event| {
+//!       if let CheckerEvent::HashMismatch(error) = event { panic!("Memory Error: {:#?}", error) }
+//!     }).unwrap()
 //!   });
 //! ```
 //!
-//! The call to [run()](ProcessConsistencyChecker::run) only returns when it encounters (non-memory) errors. If a diverging hash
-//! is found, the provided callback is called with additional info, including which library/binary was affected.
+//! The call to [run()](ProcessConsistencyChecker::run) only returns when it encounters (non-memory) errors, or when the callback
+//! returns [CheckerAction::Stop]. If a diverging hash
+//! is found, the provided callback is called with a [HashMismatch](CheckerEvent::HashMismatch) event containing additional info,
+//! including which library/binary was affected. The callback also receives the other [CheckerEvent]s, e.g. when libraries
+//! are loaded or unloaded and after every completed scan.
 //!
 //! # SAFETY
 //!
@@ -21,45 +26,124 @@
 //! Gernerally this is not a problem, but if you unload a shared library (e.g. by calling FreeLibrary on Windows, or dlclose on Linux) this causes
 //! race conditions that can lead to this library reading unmapped memory
 //!
-//! Running with [skip_libs(true)](ProcessConsistencyChecker::skip_libs) should be safe even in the presence of FreeLibrary/dlclose calls
+//! Running with [skip_libs(true)](ProcessConsistencyChecker::skip_libs) should be safe even in the presence of FreeLibrary/dlclose calls.
+//!
+//! On Linux, [safe_reads(true)](ProcessConsistencyChecker::safe_reads) makes checking libraries safe as well: all memory the checker reads,
+//! for hashing, repair and GOT checks, is copied with `process_vm_readv`, and regions that were unmapped meanwhile are reported as
+//! [RegionVanished](CheckerEvent::RegionVanished) instead of crashing the process
 //!
 //! # Advanced Usage
 //!
 //! You can decrease the search radius, e.g. if you are not concerned about shared libraries (including those of your OS) you can use
 //!
 //! ```rust
-//!   use process_consistency::ProcessConsistencyChecker;
+//!   use process_consistency::{CheckerEvent, ProcessConsistencyChecker};
 //!   std::thread::spawn(|| {
 //!     ProcessConsistencyChecker::new()
 //!       .skip_libs(true)
 //!       .search_once(true)
-//!       .run(|error| {panic!("Memory Error: {:#?}", &error)
-//!   }).unwrap()});
+//!       .run(|event| {
+//!         if let CheckerEvent::HashMismatch(error) = event { panic!("Memory Error: {:#?}", error) }
+//!       }).unwrap()
+//!   });
 //! ```
 //!
 //! On the other hand if you are paranoid, you might find situations where also considering pages marked as executable but writable is desirable:
 //!
 //! ```rust
-//!   use process_consistency::ProcessConsistencyChecker;
+//!   use process_consistency::{CheckerEvent, ProcessConsistencyChecker};
 //!   std::thread::spawn(|| {
 //!     ProcessConsistencyChecker::new()
 //!       .include_writable_code(true)
-//!       .run(|error| {panic!("Memory Error: {:#?}", &error)}).unwrap()
+//!       .run(|event| {
+//!         if let CheckerEvent::HashMismatch(error) = event { panic!("Memory Error: {:#?}", error) }
+//!       }).unwrap()
 //!   });
 //! ```
 //!
+//! Bit flips in constants, lookup tables or vtables are just as harmful as in code. To also check read-only data, use
+//! [include_readonly_data(true)](ProcessConsistencyChecker::include_readonly_data), or pick exactly which kinds of
+//! segments to check with [segments](ProcessConsistencyChecker::segments).
+//!
+//! Calls can also be redirected without touching code, by overwriting an entry of the global offset table.
+//! [check_got(true)](ProcessConsistencyChecker::check_got) verifies these entries against the dynamic linker's
+//! symbol resolution on Linux.
+//!
 //! You can also change how often the checks should be run:
 //!
 //! ```rust
 //!   use std::time::Duration;
-//!   use process_consistency::ProcessConsistencyChecker;
+//!   use process_consistency::{CheckerEvent, ProcessConsistencyChecker};
 //!   std::thread::spawn(|| {
 //!     ProcessConsistencyChecker::new()
 //!       .check_period(Duration::from_secs(60))
-//!       .run(|error| {panic!("Memory Error: {:#?}", &error)}).unwrap()
+//!       .run(|event| {
+//!         if let CheckerEvent::HashMismatch(error) = event { panic!("Memory Error: {:#?}", error) }
+//!       }).unwrap()
 //!   });
 //! ```
 //!
+//! If a region is large, knowing that it changed might not be enough. With a [chunk_size](ProcessConsistencyChecker::chunk_size)
+//! every chunk of the region is hashed separately, and mismatches report the offsets of the chunks that changed:
+//!
+//! ```rust
+//!   use process_consistency::{CheckerEvent, ProcessConsistencyChecker};
+//!   std::thread::spawn(|| {
+//!     ProcessConsistencyChecker::new()
+//!       .chunk_size(Some(4096))
+//!       .run(|event| {
+//!         if let CheckerEvent::HashMismatch(error) = event { panic!("Memory Error at {:x?}: {:#?}", error.changed_offsets, error) }
+//!       }).unwrap()
+//!   });
+//! ```
+//!
+//! To find out exactly which bytes changed, e.g. to tell a single bit flip apart from a software patch, enable
+//! [golden_copy(true)](ProcessConsistencyChecker::golden_copy). This keeps a private copy of every region, which can be
+//! compressed with the `compression` feature.
+//!
+//! With [symbolize(true)](ProcessConsistencyChecker::symbolize) mismatches also report which functions the changed bytes
+//! or chunks belong to, as `function+offset`. The `dwarf` feature adds source file and line, if debug info is available.
+//!
+//! For long running processes, corrupted code can also be [repaired](ProcessConsistencyChecker::repair) in place
+//! instead of just being reported, e.g. with `.repair(RepairStrategy::GoldenCopy)`. To avoid keeping a full copy,
+//! `RepairStrategy::Parity` only keeps XOR parity over groups of pages, which can restore one corrupted page per group.
+//!
+//! If the checker should be shut down again at some point, start it with [spawn()](ProcessConsistencyChecker::spawn) instead.
+//! This starts the checker on a background thread and returns a [CheckerHandle] that can stop, pause and resume it:
+//!
+//! ```rust
+//!   use process_consistency::{CheckerEvent, ProcessConsistencyChecker};
+//!   let handle = ProcessConsistencyChecker::new()
+//!     .spawn(|event| {
+//!       if let CheckerEvent::HashMismatch(error) = event { panic!("Memory Error: {:#?}", error) }
+//!     })
+//!     .unwrap();
+//!   // ...
+//!   handle.stop();
+//!   handle.join().unwrap();
+//! ```
+//!
+//! A running checker can also check memory that isn't code, like large immutable lookup tables or model weights. Pass
+//! the buffer to [register_region](CheckerHandle::register_region), and it is hashed and reported like any other region.
+//!
+//! Memory that comes and goes, like JIT arenas, can be supplied by a [RegionProvider](ProcessConsistencyChecker::region_provider)
+//! instead. [MockRegionProvider] serves synthetic regions, to test the handling of events without real memory corruption.
+//!
+//! Smaller values like configuration or keys can be wrapped in [Sealed], which hashes them through their `Hash`
+//! implementation, or byte for byte for [PlainData] like float arrays. All running checkers verify sealed values every round, and they can be verified on every access too.
+//!
+//! ```rust
+//!   use process_consistency::Sealed;
+//!   let config = Sealed::new("config", vec![("endpoint", "localhost")]).verify_on_deref(true);
+//!   assert_eq!(config[0].1, "localhost");
+//! ```
+//!
+//! Processes that unload shared libraries, like plugin hosts, should take an [unload_guard](CheckerHandle::unload_guard)
+//! before calling `dlclose`, so the checker never reads a library while it is unmapped.
+//!
+//! The checker opens the files it needs when it is started. Processes that lock themselves down with seccomp afterwards
+//! can get the system calls a running checker makes from [steady_state_syscalls](ProcessConsistencyChecker::steady_state_syscalls).
+//!
 //! To get a rough idea of the implications of the chosen parameters, or just to figure out which shared libraries are loaded (hint: more than you think), there is a [benchmark](ProcessConsistencyChecker::benchmark) call
 //!
 //! ```rust
@@ -92,9 +176,12 @@
 
 #![deny(unsafe_op_in_unsafe_fn)]
 
-use std::{collections::HashMap, time::Instant};
+use std::{borrow::Cow, collections::HashMap, sync::Arc, time::Instant};
 
 use error::Error;
+use golden::GoldenCopy;
+use handle::Control;
+use parity::Parity;
 
 #[cfg(unix)]
 mod linux;
@@ -102,6 +189,25 @@ mod linux;
 mod windows;
 
 pub mod error;
+mod golden;
+mod handle;
+mod parity;
+mod provider;
+mod repair;
+mod sealed;
+mod snapshot;
+mod symbols;
+
+pub use golden::ByteDiff;
+pub use handle::{CheckerHandle, RegionId, UnloadGuard};
+pub use provider::{MockRegionProvider, ProviderError, RegionProvider};
+pub use repair::RepairStrategy;
+pub use sealed::{PlainData, Sealed};
+pub use snapshot::{Snapshot, SnapshotDiff, SnapshotEntry, VerifyReport};
+pub use symbols::Location;
+
+#[cfg(unix)]
+pub use linux::maps;
 
 #[cfg(feature = "blake3")]
 type HashInner = [u8; 32];
@@ -119,55 +225,355 @@ impl Hash {
     pub fn inner(self) -> HashInner {
         self.0
     }
+
+    fn of(slice: &[u8]) -> Self {
+        #[cfg(feature = "blake3")]
+        return Hash(blake3::hash(slice).into());
+        #[cfg(all(not(feature = "blake3"), feature = "crc64"))]
+        {
+            let mut digest = crc64fast::Digest::new();
+            digest.write(slice);
+            Hash(digest.sum64())
+        }
+    }
+
+    /// hash a list of hashes, e.g. to get a single hash for a region hashed in chunks
+    fn combine(hashes: &[Hash]) -> Self {
+        #[cfg(feature = "blake3")]
+        {
+            let mut hasher = blake3::Hasher::new();
+            for hash in hashes {
+                hasher.update(&hash.0);
+            }
+            Hash(hasher.finalize().into())
+        }
+        #[cfg(all(not(feature = "blake3"), feature = "crc64"))]
+        {
+            let mut digest = crc64fast::Digest::new();
+            for hash in hashes {
+                digest.write(&hash.0.to_le_bytes());
+            }
+            Hash(digest.sum64())
+        }
+    }
 }
 
 /// A hashed memory region
 #[derive(Debug, Hash, PartialEq, Eq, Clone)]
 pub struct Region {
     /// first address of the region
-    pub start: *const u8,
+    pub start: usize,
     /// last address of the region + 1
-    pub end: *const u8,
+    pub end: usize,
     /// where does this code come from (usually a valid Path)
     pub source: String,
+    /// how the region is mapped
+    pub permissions: Permissions,
+    /// what the region contains
+    pub segment: Segment,
+    /// the file the region is mapped from, None for anonymous memory
+    pub file: Option<MappedFile>,
+}
+
+/// Access permissions of a memory mapping
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Permissions {
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+    /// true for shared mappings, false for private (copy on write) ones
+    pub shared: bool,
+}
+
+/// What a [Region] contains
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Segment {
+    /// executable code
+    Text,
+    /// read-only data like `.rodata`: constants, lookup tables and string literals
+    ReadOnlyData,
+    /// data that the dynamic loader makes read-only after relocating it, like vtables and the GOT (Linux only)
+    Relro,
+    /// memory registered with [CheckerHandle::register_region]
+    Custom,
+    /// a value wrapped in [Sealed]. The region is where the value itself lives, see [Sealed::new] for what is hashed
+    Sealed,
+}
+
+/// Which kinds of [Segment]s are checked, see [segments](ProcessConsistencyChecker::segments)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Segments {
+    pub text: bool,
+    pub readonly_data: bool,
+    pub relro: bool,
+}
+
+impl Segments {
+    pub(crate) fn includes(&self, segment: Segment) -> bool {
+        match segment {
+            Segment::Text => self.text,
+            Segment::ReadOnlyData => self.readonly_data,
+            Segment::Relro => self.relro,
+            // registered regions and sealed values are always checked
+            Segment::Custom | Segment::Sealed => true,
+        }
+    }
+}
+
+impl Default for Segments {
+    /// only code
+    fn default() -> Self {
+        Self {
+            text: true,
+            readonly_data: false,
+            relro: false,
+        }
+    }
+}
+
+/// Identifies the file a [Region] is mapped from
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MappedFile {
+    /// offset of the region's first byte in the file. On Windows this is the offset from the module base instead
+    pub offset: u64,
+    /// major and minor number of the device containing the file, (0, 0) on Windows
+    pub device: (u32, u32),
+    /// inode of the file, 0 on Windows
+    pub inode: u64,
+    /// address the start of the file is loaded at
+    pub module_base: usize,
+    /// the ELF GNU build-id of the file, if it has one (Linux only)
+    pub build_id: Option<Vec<u8>>,
 }
 
+#[derive(Debug, Clone)]
 struct RegionHash {
     hash: Hash,
+    /// hashes of each chunk of the region, empty unless a chunk_size is configured
+    chunks: Vec<Hash>,
     computed_at: std::time::Instant,
+    /// when the region was last found during discovery
+    seen_at: std::time::Instant,
+    /// copy of the region's contents, only kept if golden_copy is configured
+    golden: Option<Arc<GoldenCopy>>,
+    /// parity over the region's chunks, only kept if parity repair is configured
+    parity: Option<Arc<Parity>>,
+}
+
+impl RegionHash {
+    /// hash a region, either as a whole or in chunks of chunk_size bytes. Ok(None) if the region was unmapped, see
+    /// [Region::read]
+    ///
+    /// # SAFETY
+    /// same as [Region::read]
+    unsafe fn compute(
+        region: &Region,
+        config: &CheckerConfig,
+        now: Instant,
+    ) -> Result<Option<Self>, Error> {
+        let bytes = unsafe { region.read(config) }?;
+        Ok(bytes.map(|bytes| Self::of_bytes(&bytes, config, now)))
+    }
+
+    /// hash the contents of a region, e.g. as read from disk
+    fn of_bytes(bytes: &[u8], config: &CheckerConfig, now: Instant) -> Self {
+        let (hash, chunks) = match config.effective_chunk_size() {
+            None => (Hash::of(bytes), vec![]),
+            Some(chunk_size) => {
+                let chunks: Vec<_> = bytes.chunks(chunk_size).map(Hash::of).collect();
+                (Hash::combine(&chunks), chunks)
+            }
+        };
+        Self {
+            hash,
+            chunks,
+            computed_at: now,
+            seen_at: now,
+            golden: None,
+            parity: None,
+        }
+    }
+
+    /// keep a copy or parity of the region's contents if configured, to later find out exactly which bytes changed
+    /// or to repair them
+    fn keep_repair_data(&mut self, bytes: &[u8], config: &CheckerConfig) {
+        if config.keeps_golden_copy() {
+            self.golden = Some(Arc::new(GoldenCopy::new(
+                bytes,
+                config.compress_golden_copy,
+            )));
+        }
+        if let (RepairStrategy::Parity { group_size }, Some(chunk_size)) =
+            (config.repair, config.effective_chunk_size())
+        {
+            self.parity = Some(Arc::new(Parity::new(bytes, chunk_size, group_size)));
+        }
+    }
+
+    /// indices of all chunks that differ between this hash and `current`
+    fn changed_chunks(&self, current: &RegionHash) -> Vec<usize> {
+        self.chunks
+            .iter()
+            .zip(&current.chunks)
+            .enumerate()
+            .filter(|(_, (old, new))| old != new)
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// describe how `current` differs from this hash. `current_bytes` is the region's current content, used to
+    /// compute the exact differences if there is a golden copy
+    fn mismatch(
+        &self,
+        region: &Region,
+        current: &RegionHash,
+        config: &CheckerConfig,
+        current_bytes: Option<&[u8]>,
+    ) -> MemoryEvent {
+        let changed_chunks = self.changed_chunks(current);
+        let changed_offsets = match config.effective_chunk_size() {
+            Some(chunk_size) => changed_chunks.iter().map(|i| i * chunk_size).collect(),
+            None => vec![],
+        };
+        let byte_diffs = match (&self.golden, current_bytes) {
+            (Some(golden), Some(current_bytes)) => {
+                golden::byte_diffs(&golden.bytes(), current_bytes)
+            }
+            _ => vec![],
+        };
+        let locations = if config.symbolize {
+            // exact bytes if known, otherwise the start of each changed chunk
+            let offsets: Vec<_> = if byte_diffs.is_empty() {
+                changed_offsets.clone()
+            } else {
+                byte_diffs.iter().map(|diff| diff.offset).collect()
+            };
+            symbols::symbolize(region, &offsets)
+        } else {
+            vec![]
+        };
+        MemoryEvent {
+            region: region.clone(),
+            old_hash: self.hash,
+            new_hash: current.hash,
+            old_hash_computed_at: self.computed_at,
+            changed_offsets,
+            flipped_bits: byte_diffs.iter().map(ByteDiff::flipped_bits).sum(),
+            byte_diffs,
+            locations,
+            correctable: self
+                .parity
+                .as_ref()
+                .map(|parity| parity.can_correct(&changed_chunks)),
+        }
+    }
 }
 
 impl Region {
-    /// compute hash of a memory region
+    /// access the contents of a memory region
     ///
     /// # SAFETY
     /// this is only safe if the module is still loaded, otherwise this might dereference and access unmapped memory
     /// there seems to be no mechanism to ensure this, other than making the entire appliation pinky-promise never to call
     /// FreeLibrary, dlclose or similar
-    unsafe fn compute_hash(&self) -> Hash {
+    unsafe fn as_slice(&self) -> &[u8] {
         // SAFETY: this should be safe iff the module hasn't been unloaded yet.
         // but there's no mechanism to ensure this
-        let slice = unsafe {
-            std::slice::from_raw_parts(self.start, self.end.offset_from(self.start) as usize)
-        };
+        unsafe { std::slice::from_raw_parts(self.start as *const u8, self.end - self.start) }
+    }
 
-        #[cfg(feature = "blake3")]
-        return Hash(blake3::hash(slice).into());
-        #[cfg(all(not(feature = "blake3"), feature = "crc64"))]
-        {
-            let mut digest = crc64fast::Digest::new();
-            digest.write(slice);
-            Hash(digest.sum64())
+    /// the contents of the region, copied with [safe_reads](ProcessConsistencyChecker::safe_reads) if configured.
+    /// Ok(None) if the region was unmapped, which can only be detected with safe reads
+    ///
+    /// # SAFETY
+    /// same as [Region::as_slice], unless safe reads are configured
+    unsafe fn read(&self, config: &CheckerConfig) -> Result<Option<Cow<'_, [u8]>>, Error> {
+        #[cfg(unix)]
+        if config.safe_reads {
+            return Ok(linux::read_memory(self.start, self.end - self.start)?.map(Cow::Owned));
         }
+        #[cfg(windows)]
+        let _ = config;
+        Ok(Some(Cow::Borrowed(unsafe { self.as_slice() })))
+    }
+
+    /// offset of an address in this region from the load base of its module, e.g. to pass to `addr2line`.
+    ///
+    /// None if the address is outside of the region, or the region isn't mapped from a file
+    pub fn module_offset(&self, address: usize) -> Option<usize> {
+        let file = self.file.as_ref()?;
+        (self.start..self.end)
+            .contains(&address)
+            .then(|| address - file.module_base)
     }
 }
 
 #[derive(Default, Clone, Debug, Hash, PartialEq, Eq)]
 struct CheckerConfig {
     search_once: bool,
+    rediscovery_period: Option<std::time::Duration>,
     skip_libs: bool,
     check_period: std::time::Duration,
     include_writable_code: bool,
+    segments: Segments,
+    chunk_size: Option<usize>,
+    golden_copy: bool,
+    compress_golden_copy: bool,
+    repair: RepairStrategy,
+    verify_against_file: bool,
+    symbolize: bool,
+    check_got: bool,
+    safe_reads: bool,
+    #[cfg_attr(windows, allow(dead_code))]
+    unbacked_mappings: UnbackedMappings,
+    #[cfg_attr(windows, allow(dead_code))]
+    discovery: Discovery,
+    skip_system_regions: bool,
+    providers: provider::Providers,
+}
+
+impl CheckerConfig {
+    /// whether regions of this segment are hashed. Checking the GOT includes RELRO, where it usually lives
+    fn checks_segment(&self, segment: Segment) -> bool {
+        self.segments.includes(segment) || segment == Segment::Relro && self.check_got
+    }
+
+    fn keeps_golden_copy(&self) -> bool {
+        self.golden_copy || self.repair == RepairStrategy::GoldenCopy
+    }
+
+    /// the configured chunk size. Parity repair works on chunks, so it falls back to the page size
+    fn effective_chunk_size(&self) -> Option<usize> {
+        match self.repair {
+            RepairStrategy::Parity { .. } => self.chunk_size.or(Some(4096)),
+            _ => self.chunk_size,
+        }
+    }
+}
+
+/// How executable mappings that don't belong to a file are treated during discovery, see
+/// [unbacked_mappings](ProcessConsistencyChecker::unbacked_mappings). Only relevant on Linux
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum UnbackedMappings {
+    /// ignore them
+    Skip,
+    /// check pseudo mappings like `[vdso]`, but ignore anonymous ones, including named ones like `[anon:jit]`
+    #[default]
+    PseudoOnly,
+    /// check both pseudo and anonymous mappings. Anonymous code is often generated at runtime, so expect changes
+    All,
+}
+
+/// How regions are found, see [discovery](ProcessConsistencyChecker::discovery). Only relevant on Linux
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Discovery {
+    /// parse `/proc/self/maps`, which includes code that doesn't belong to a loaded object, like JIT output
+    #[default]
+    ProcMaps,
+    /// walk the objects known to the dynamic loader with `dl_iterate_phdr`, and use their program headers. This works
+    /// without procfs, e.g. in minimal chroots, and gives exact segment boundaries instead of whole pages. Anonymous
+    /// code isn't found
+    LoadedObjects,
 }
 
 /// Config Builder
@@ -194,6 +600,17 @@ impl ProcessConsistencyChecker {
         self
     }
 
+    /// only rediscover code regions when shared libraries were loaded or unloaded, and at least once per `period`
+    /// (Default: rediscover every round)
+    ///
+    /// Loading and unloading is detected cheaply through the dynamic loader (only on Linux, elsewhere regions are
+    /// rediscovered every round). Other changes to the mappings, like newly generated anonymous code, are only found
+    /// by the periodic rediscovery. Ignored with [search_once](Self::search_once)
+    pub fn rediscovery_period(&mut self, period: std::time::Duration) -> &mut Self {
+        self.config.rediscovery_period = Some(period);
+        self
+    }
+
     /// if set to true, only inspect code regions that belong to the binary itself, skipping dynamic libraries (Default: false)
     pub fn skip_libs(&mut self, skip_libs: bool) -> &mut Self {
         self.config.skip_libs = skip_libs;
@@ -212,10 +629,238 @@ impl ProcessConsistencyChecker {
         self
     }
 
-    /// start running checks. Calls error_callback whenever the hash of a memory region changes. If hashes can't be
-    /// calculated returns an Error, otherwise it doesn't return
-    pub fn run(&self, error_callback: ErrorCallback) -> Result<Never, Error> {
-        run_checker(&self.config, error_callback)
+    /// also check read-only data mapped from files, i.e. [Segment::ReadOnlyData] and [Segment::Relro] (default: false)
+    ///
+    /// Constants, lookup tables, vtables and string literals are as important for correct behavior as code, and
+    /// a bit flip there is just as harmful. Shorthand for enabling both in [segments](Self::segments)
+    pub fn include_readonly_data(&mut self, include_readonly_data: bool) -> &mut Self {
+        self.config.segments.readonly_data = include_readonly_data;
+        self.config.segments.relro = include_readonly_data;
+        self
+    }
+
+    /// select exactly which kinds of segments are checked (default: only [Segment::Text])
+    ///
+    /// Read-only data is only checked if it is mapped privately from a file. Note that pages of a file that were
+    /// never written to reflect changes of the file on disk, so replacing a loaded file in place shows up as a
+    /// mismatch. Windows has no [Segment::Relro]
+    pub fn segments(&mut self, segments: Segments) -> &mut Self {
+        self.config.segments = segments;
+        self
+    }
+
+    /// hash regions in chunks of chunk_size bytes instead of as a whole (default: None, i.e. as a whole)
+    ///
+    /// With a chunk size, e.g. the page size of 4096 bytes, [HashMismatch](CheckerEvent::HashMismatch) events report
+    /// the offsets of the chunks that changed, instead of just the region. This costs one stored hash per chunk.
+    /// [RepairStrategy::Parity] uses 4096 if no chunk size is configured
+    ///
+    /// Panics if chunk_size is `Some(0)`
+    pub fn chunk_size(&mut self, chunk_size: Option<usize>) -> &mut Self {
+        assert_ne!(chunk_size, Some(0), "chunk_size must not be 0");
+        self.config.chunk_size = chunk_size;
+        self
+    }
+
+    /// keep a private copy of every region, to report exactly which bytes changed (default: false)
+    ///
+    /// With a golden copy, [HashMismatch](CheckerEvent::HashMismatch) events contain the offset, old and new value of
+    /// every changed byte, and the number of flipped bits. This makes it easy to tell a single bit flip apart from a
+    /// software patch, but doubles the memory used for the checked regions unless
+    /// [compressed](Self::compress_golden_copy)
+    pub fn golden_copy(&mut self, golden_copy: bool) -> &mut Self {
+        self.config.golden_copy = golden_copy;
+        self
+    }
+
+    /// compress the [golden copy](Self::golden_copy) of every region (default: false)
+    ///
+    /// Saves memory at the cost of some cpu time when the copy is created and when a mismatch is found
+    #[cfg(feature = "compression")]
+    pub fn compress_golden_copy(&mut self, compress_golden_copy: bool) -> &mut Self {
+        self.config.compress_golden_copy = compress_golden_copy;
+        self
+    }
+
+    /// try to repair regions whose hash changed (default: [RepairStrategy::Disabled])
+    ///
+    /// After a [HashMismatch](CheckerEvent::HashMismatch) the original contents are written back, and the region is
+    /// hashed again to confirm the repair. The outcome is reported as [Repaired](CheckerEvent::Repaired) or
+    /// [RepairFailed](CheckerEvent::RepairFailed). Either way the old hash stays the baseline, so a failed repair is
+    /// retried on the next round. Repair is skipped if the callback returns [CheckerAction::AcceptNewBaseline]
+    /// or [CheckerAction::Stop] for the mismatch.
+    ///
+    /// [RepairStrategy::GoldenCopy] keeps a private copy of every region, same as [golden_copy](Self::golden_copy)
+    ///
+    /// Panics if [RepairStrategy::Parity] has a group_size of 0
+    pub fn repair(&mut self, repair: RepairStrategy) -> &mut Self {
+        assert_ne!(
+            repair,
+            RepairStrategy::Parity { group_size: 0 },
+            "group_size must not be 0"
+        );
+        self.config.repair = repair;
+        self
+    }
+
+    /// compare every newly found region to the file it was loaded from (default: false, only supported on Linux)
+    ///
+    /// Normally the first hash of a region is trusted. With this option, memory that was already modified before the
+    /// checker saw it is reported as [FileMismatch](CheckerEvent::FileMismatch). Bytes patched by text relocations
+    /// are ignored. Regions that aren't backed by a file, or whose file was replaced on disk, can't be compared
+    /// and are skipped, as is [Segment::Relro], which the loader fills with relocated pointers
+    pub fn verify_against_file(&mut self, verify_against_file: bool) -> &mut Self {
+        self.config.verify_against_file = verify_against_file;
+        self
+    }
+
+    /// copy memory with `process_vm_readv` before hashing it, instead of reading it directly (default: false, only
+    /// supported on Linux)
+    ///
+    /// Reading a region that was unmapped since it was found, e.g. because another thread unloaded a library, crashes
+    /// the process. With safe reads this is reported as [RegionVanished](CheckerEvent::RegionVanished) instead, so
+    /// libraries can be checked in processes that load and unload plugins. This covers every read of checked memory,
+    /// including [repair](Self::repair), [check_got](Self::check_got) and [benchmark](Self::benchmark). Costs an extra
+    /// copy of every region
+    pub fn safe_reads(&mut self, safe_reads: bool) -> &mut Self {
+        self.config.safe_reads = safe_reads;
+        self
+    }
+
+    /// verify the global offset table of every module after each round (default: false, only supported on Linux)
+    ///
+    /// Overwriting a GOT entry redirects calls just like patching code. Every entry for a function is compared to
+    /// where `dlsym` resolves the symbol to, and reported as [GotMismatch](CheckerEvent::GotMismatch) if it points
+    /// to other code, or [GotEntryOutsideCode](CheckerEvent::GotEntryOutsideCode) if it doesn't point to executable
+    /// memory at all. Every bad value is reported once. This also hashes the [Segment::Relro] of every module,
+    /// which contains the GOT and other pointer tables like vtables if the module was linked with full RELRO
+    pub fn check_got(&mut self, check_got: bool) -> &mut Self {
+        self.config.check_got = check_got;
+        self
+    }
+
+    /// resolve where memory changed to function names and source lines (default: false, only supported on Linux)
+    ///
+    /// [HashMismatch](CheckerEvent::HashMismatch) events then list the function and offset of every changed byte
+    /// (with a [golden copy](Self::golden_copy)) or chunk (with a [chunk_size](Self::chunk_size)), using the
+    /// `.symtab` and `.dynsym` of the file the region was loaded from. With the `dwarf` feature they also contain the
+    /// source file and line, if the file has debug info. Consecutive changes in the same function are reported once
+    pub fn symbolize(&mut self, symbolize: bool) -> &mut Self {
+        self.config.symbolize = symbolize;
+        self
+    }
+
+    /// which executable mappings without a file to check (default: [UnbackedMappings::PseudoOnly], only used on Linux)
+    ///
+    /// Pseudo mappings like `[vdso]` are provided by the kernel and shouldn't change. Anonymous executable memory is
+    /// typically created by JIT compilers or loaders, and changes whenever they generate new code
+    pub fn unbacked_mappings(&mut self, unbacked_mappings: UnbackedMappings) -> &mut Self {
+        self.config.unbacked_mappings = unbacked_mappings;
+        self
+    }
+
+    /// how regions are found (default: [Discovery::ProcMaps], only used on Linux)
+    pub fn discovery(&mut self, discovery: Discovery) -> &mut Self {
+        self.config.discovery = discovery;
+        self
+    }
+
+    /// start running checks. Calls event_callback for every [CheckerEvent], most importantly whenever the hash of a
+    /// memory region changes. If hashes can't be calculated returns an Error. Otherwise it only returns if the callback
+    /// returns [CheckerAction::Stop]
+    ///
+    /// The callback can be any closure, so it's free to capture loggers, counters, channels etc. It can either return
+    /// nothing, or a [CheckerAction] to decide how the checker should proceed
+    ///
+    /// ```rust
+    ///   use process_consistency::{CheckerAction, CheckerEvent, ProcessConsistencyChecker};
+    ///   ProcessConsistencyChecker::new().run(|event| match event {
+    ///     // keep comparing against the original hash, so the change is reported again on the next round
+    ///     CheckerEvent::HashMismatch(_) => CheckerAction::KeepOldBaseline,
+    ///     CheckerEvent::ScanCompleted { .. } => CheckerAction::Stop,
+    ///     _ => CheckerAction::Continue,
+    ///   }).unwrap();
+    /// ```
+    pub fn run<A: Into<CheckerAction>>(
+        &self,
+        event_callback: impl FnMut(&CheckerEvent) -> A,
+    ) -> Result<(), Error> {
+        #[cfg(unix)]
+        linux::prepare(&self.config)?;
+        run_checker(&self.config, event_callback, &Control::new(&self.config))
+    }
+
+    /// start running checks on a background thread. Calls event_callback for every [CheckerEvent], see [run()](Self::run).
+    ///
+    /// The returned [CheckerHandle] can be used to stop, pause and resume the checker
+    ///
+    /// ```rust
+    ///   use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
+    ///   use process_consistency::{CheckerEvent, ProcessConsistencyChecker};
+    ///   let error_count = Arc::new(AtomicUsize::new(0));
+    ///   let counter = error_count.clone();
+    ///   let handle = ProcessConsistencyChecker::new()
+    ///     .spawn(move |event| {
+    ///       if let CheckerEvent::HashMismatch(error) = event {
+    ///         counter.fetch_add(1, Ordering::Relaxed);
+    ///         eprintln!("Memory Error: {:#?}", error);
+    ///       }
+    ///     })
+    ///     .unwrap();
+    ///   handle.stop();
+    ///   handle.join().unwrap();
+    /// ```
+    pub fn spawn<A: Into<CheckerAction>>(
+        &self,
+        event_callback: impl FnMut(&CheckerEvent) -> A + Send + 'static,
+    ) -> Result<CheckerHandle, Error> {
+        #[cfg(unix)]
+        linux::prepare(&self.config)?;
+        let config = self.config.clone();
+        let control = Arc::new(Control::new(&self.config));
+        let thread_control = control.clone();
+        let thread = std::thread::Builder::new()
+            .name("process_consistency".into())
+            .spawn(move || run_checker(&config, event_callback, &thread_control))
+            .map_err(|e| Error::ThreadSpawnError { source: e })?;
+        Ok(CheckerHandle::new(control, thread))
+    }
+
+    /// check the regions of `provider` as well, e.g. JIT arenas or buffers owned by a library. It is asked for its
+    /// regions every round, even with [search_once](Self::search_once). Can be called multiple times
+    pub fn region_provider(&mut self, provider: impl RegionProvider + 'static) -> &mut Self {
+        self.config.providers.0.push(Arc::new(provider));
+        self
+    }
+
+    /// find code and data regions of the process (default: true). Disable this to only check the regions of
+    /// [region providers](Self::region_provider), e.g. a [MockRegionProvider] in tests
+    pub fn system_regions(&mut self, system_regions: bool) -> &mut Self {
+        self.config.skip_system_regions = !system_regions;
+        self
+    }
+
+    /// the system calls a checker with this configuration makes once it is running, by their Linux names (Linux only)
+    ///
+    /// [run()](Self::run) and [spawn()](Self::spawn) open everything discovery needs before the first round, and
+    /// reread `/proc/self/maps` with `pread` from then on. This makes it possible to lock down the process with
+    /// seccomp or Landlock after the checker started, with this list as allowlist. Features that read the files code
+    /// was loaded from, and discovery of shared libraries loaded later, need to open files as well
+    ///
+    /// ```rust
+    ///   use process_consistency::ProcessConsistencyChecker;
+    ///   let syscalls = ProcessConsistencyChecker::new().search_once(true).steady_state_syscalls();
+    ///   assert!(syscalls.contains(&"pread64"));
+    ///   assert!(!syscalls.contains(&"openat"));
+    /// ```
+    #[cfg(unix)]
+    pub fn steady_state_syscalls(&self) -> Vec<&'static str> {
+        linux::steady_state_syscalls(&self.config)
+    }
+
+    /// hash all regions selected by this configuration once, e.g. to [verify](Snapshot::verify) them later
+    pub fn capture_snapshot(&self) -> Result<Snapshot, Error> {
+        Snapshot::capture_with(&self.config, vec![], None)
     }
 
     /// start benchmark. Runs a single round of hashing and returns statistics
@@ -231,84 +876,419 @@ impl Default for ProcessConsistencyChecker {
 }
 
 /// Details about an encountered memory inconsistency
+///
+/// It is `Send` and `'static`, so it can be passed to other threads or kept around after the callback returned
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub struct MemoryError<'a> {
-    /// the address, size and origin of the region where the error occurred
-    pub region: &'a Region,
+pub struct MemoryEvent {
+    /// the address, size, origin and file metadata of the region where the error occurred
+    pub region: Region,
     /// the previous hash of the region
     pub old_hash: Hash,
     /// the current hash of the region
     pub new_hash: Hash,
     /// when old_hash was computed
     pub old_hash_computed_at: std::time::Instant,
+    /// offsets from the start of the region of all chunks that changed. Empty unless a
+    /// [chunk_size](ProcessConsistencyChecker::chunk_size) is configured
+    pub changed_offsets: Vec<usize>,
+    /// every byte that differs from the golden copy. Empty unless
+    /// [golden_copy](ProcessConsistencyChecker::golden_copy) is enabled
+    pub byte_diffs: Vec<ByteDiff>,
+    /// total number of bits that differ from the golden copy
+    pub flipped_bits: u32,
+    /// functions and source lines of the changed bytes or chunks. Empty unless
+    /// [symbolize](ProcessConsistencyChecker::symbolize) is enabled
+    pub locations: Vec<Location>,
+    /// whether the changed chunks can be rebuilt from parity, i.e. at most one chunk per parity group changed.
+    /// None unless [RepairStrategy::Parity] is configured
+    pub correctable: Option<bool>,
+}
+
+/// A suspicious entry of a module's global offset table, see [check_got](ProcessConsistencyChecker::check_got)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GotEntry {
+    /// the module the GOT belongs to
+    pub module: String,
+    /// address of the entry
+    pub address: usize,
+    /// the symbol the entry was resolved for
+    pub symbol: String,
+    /// where the entry points to
+    pub value: usize,
+    /// where the symbol resolves to according to `dlsym`, if it can be resolved
+    pub expected: Option<usize>,
+}
+
+/// Everything the checker reports to the event callback and to [subscribers](CheckerHandle::subscribe)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum CheckerEvent {
+    /// a region was found for the first time, e.g. because a library was loaded. Emitted for every region during the first scan
+    RegionAdded(Region),
+    /// a previously known region disappeared, e.g. because a library was unloaded
+    RegionRemoved(Region),
+    /// a region was unmapped between discovery and hashing, e.g. because a library was unloaded on another thread.
+    /// Only detected with [safe_reads](ProcessConsistencyChecker::safe_reads), otherwise this crashes the process
+    RegionVanished(Region),
+    /// the hash of a region changed
+    HashMismatch(MemoryEvent),
+    /// a newly found region differs from the file it was loaded from,
+    /// see [verify_against_file](ProcessConsistencyChecker::verify_against_file)
+    FileMismatch {
+        /// the region that differs
+        region: Region,
+        /// every differing byte. [old](ByteDiff::old) is the value on disk, [new](ByteDiff::new) the value in memory
+        byte_diffs: Vec<ByteDiff>,
+    },
+    /// a region with a [HashMismatch](Self::HashMismatch) was restored to its original contents,
+    /// see [repair()](ProcessConsistencyChecker::repair)
+    Repaired {
+        /// the repaired region
+        region: Region,
+        /// how many bytes were rewritten
+        bytes: usize,
+    },
+    /// a region with a [HashMismatch](Self::HashMismatch) could not be repaired,
+    /// see [repair()](ProcessConsistencyChecker::repair)
+    RepairFailed {
+        /// the corrupted region
+        region: Region,
+        /// why the repair failed
+        reason: String,
+    },
+    /// a GOT entry points to other code than the dynamic linker resolves its symbol to,
+    /// see [check_got](ProcessConsistencyChecker::check_got)
+    GotMismatch(GotEntry),
+    /// a GOT entry points outside of any executable memory, see [check_got](ProcessConsistencyChecker::check_got)
+    GotEntryOutsideCode(GotEntry),
+    /// a check round finished
+    ScanCompleted {
+        /// how long discovery and hashing took
+        duration: std::time::Duration,
+        /// how many bytes were hashed
+        bytes: usize,
+        /// how many regions were hashed
+        regions: usize,
+    },
+    /// a check round took longer than the configured [check_period](ProcessConsistencyChecker::check_period),
+    /// so the next round starts late. Emitted right after the corresponding [ScanCompleted](Self::ScanCompleted)
+    ScanOverrun {
+        /// how long discovery and hashing took
+        duration: std::time::Duration,
+        /// the configured period between rounds
+        check_period: std::time::Duration,
+    },
+}
+
+fn get_all_regions(config: &CheckerConfig) -> Result<Vec<Region>, Error> {
+    let mut regions = get_system_regions(config)?;
+    regions.extend(get_provided_regions(config)?);
+    Ok(regions)
+}
+
+/// the regions the OS knows about
+fn get_system_regions(config: &CheckerConfig) -> Result<Vec<Region>, Error> {
+    if config.skip_system_regions {
+        return Ok(vec![]);
+    }
+    #[cfg(unix)]
+    return crate::linux::get_executable_regions(config);
+    #[cfg(windows)]
+    crate::windows::get_executable_regions(config)
 }
 
-type ErrorCallback = fn(MemoryError) -> ();
+fn get_provided_regions(config: &CheckerConfig) -> Result<Vec<Region>, Error> {
+    let mut regions = vec![];
+    for provider in &config.providers.0 {
+        regions.extend(
+            provider
+                .regions()
+                .map_err(|source| Error::RegionProviderError { source })?,
+        );
+    }
+    Ok(regions)
+}
 
-fn get_all_regions(skip_libs: bool, include_writable_code: bool) -> Result<Vec<Region>, Error> {
+/// changes whenever shared libraries are loaded or unloaded, None if that can't be detected
+fn loaded_objects_generation() -> Option<(u64, u64)> {
     #[cfg(unix)]
-    return crate::linux::get_executable_regions(skip_libs, include_writable_code);
+    return crate::linux::loaded_objects_generation();
     #[cfg(windows)]
-    crate::windows::get_executable_regions(skip_libs, include_writable_code)
+    None
 }
 
-/// Return type of functions that don't return
+/// What the checker should do after an event was handled, returned from the event callback
 ///
-/// see <https://doc.rust-lang.org/std/primitive.never.html>
-pub enum Never {}
+/// Callbacks that return `()` are treated as returning [Continue](Self::Continue)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum CheckerAction {
+    /// proceed as usual. For a [HashMismatch](CheckerEvent::HashMismatch) this accepts the new hash as baseline,
+    /// same as [AcceptNewBaseline](Self::AcceptNewBaseline), unless [repair](ProcessConsistencyChecker::repair) is
+    /// enabled: then the region is repaired and keeps the old hash, even if the repair fails
+    #[default]
+    Continue,
+    /// after a [HashMismatch](CheckerEvent::HashMismatch), keep comparing against the old hash. The mismatch
+    /// will be reported again on every following round until the memory changes back
+    KeepOldBaseline,
+    /// after a [HashMismatch](CheckerEvent::HashMismatch), treat the new hash as correct from now on
+    AcceptNewBaseline,
+    /// stop the checker immediately, without finishing the current round
+    Stop,
+    /// rediscover all regions and start the next round right away, even if [search_once](ProcessConsistencyChecker::search_once) is set.
+    /// After a [HashMismatch](CheckerEvent::HashMismatch) this keeps the old hash, like
+    /// [KeepOldBaseline](Self::KeepOldBaseline), so the rescan doesn't hide the change
+    Rescan,
+}
+
+impl From<()> for CheckerAction {
+    fn from(_: ()) -> Self {
+        Self::Continue
+    }
+}
 
-fn run_checker(
+fn run_checker<A: Into<CheckerAction>>(
     config: &CheckerConfig,
-    error_callback: ErrorCallback,
-    // stop: AtomicBool,
-) -> Result<Never, Error> {
+    mut event_callback: impl FnMut(&CheckerEvent) -> A,
+    control: &Control,
+) -> Result<(), Error> {
+    let mut emit = |event: CheckerEvent| {
+        let action = event_callback(&event).into();
+        control.publish(event);
+        action
+    };
     let mut region_hashes: HashMap<Region, RegionHash> = HashMap::new();
-    loop {
+    #[cfg(unix)]
+    let mut got = linux::GotChecker::default();
+    // hash of every sealed value that is currently reported as changed, to report every change once
+    let mut sealed_reported: HashMap<Region, Hash> = HashMap::new();
+    let mut sleep_duration = std::time::Duration::ZERO;
+    let mut rescan = false;
+    // when and for which set of loaded objects regions were last discovered
+    let mut discovered_at = Instant::now();
+    let mut discovered_generation = None;
+    let mut discovered = vec![];
+    let _finish = control.finish_on_drop();
+    while control.wait(sleep_duration, || {
+        control.answer_baseline(|| Snapshot::new(config, region_hashes.clone()))
+    }) {
         let now = std::time::Instant::now();
-        let regions = if !config.search_once || region_hashes.is_empty() {
-            get_all_regions(config.skip_libs, config.include_writable_code)?
+        // keep registered regions alive until the end of this round, even if they are unregistered meanwhile
+        let registered = control.registered_regions();
+        let generation = loaded_objects_generation();
+        let rediscover = region_hashes.is_empty()
+            || rescan
+            || (!config.search_once
+                && match config.rediscovery_period {
+                    None => true,
+                    Some(period) => {
+                        generation.is_none()
+                            || generation != discovered_generation
+                            || now.duration_since(discovered_at) >= period
+                    }
+                });
+        if rediscover {
+            discovered_at = now;
+            discovered_generation = generation;
+            let unload_count = control.unload_count();
+            discovered = get_system_regions(config)?;
+            // modules that were unloaded before discovery started can't be among the regions anymore
+            control.forget_unloaded(unload_count);
         } else {
-            region_hashes.keys().cloned().collect() // todo: optimize?
-        };
+            // forget regions that vanished or are being unloaded
+            discovered.retain(|region| region_hashes.contains_key(region));
+        }
+        let provided = get_provided_regions(config)?;
+        let regions = discovered
+            .iter()
+            .chain(&provided)
+            .chain(registered.iter().map(|(region, _)| region));
+        rescan = false;
 
+        let mut hashed_bytes = 0;
+        let region_count = discovered.len() + provided.len() + registered.len();
         for region in regions {
-            let hash = unsafe { region.compute_hash() };
+            let Some(_in_use) = control.use_module(region) else {
+                // about to be unloaded, see CheckerHandle::unload_guard
+                if region_hashes.remove(region).is_some() {
+                    match emit(CheckerEvent::RegionRemoved(region.clone())) {
+                        CheckerAction::Stop => return Ok(()),
+                        CheckerAction::Rescan => rescan = true,
+                        _ => {}
+                    }
+                }
+                continue;
+            };
+            let Some(bytes) = (unsafe { region.read(config) })? else {
+                // unmapped since it was found, e.g. by dlclose on another thread
+                region_hashes.remove(region);
+                match emit(CheckerEvent::RegionVanished(region.clone())) {
+                    CheckerAction::Stop => return Ok(()),
+                    CheckerAction::Rescan => rescan = true,
+                    _ => {}
+                }
+                continue;
+            };
+            let mut current = RegionHash::of_bytes(&bytes, config, now);
+            hashed_bytes += region.end - region.start;
 
             // don't use entry API to avoid a copy of the region
-            match region_hashes.get_mut(&region) {
+            match region_hashes.get_mut(region) {
                 Some(entry) => {
                     // check if known region is unchanged
-                    if entry.hash != hash {
-                        error_callback(MemoryError {
-                            region: &region,
-                            old_hash: entry.hash,
-                            new_hash: hash,
-                            old_hash_computed_at: entry.computed_at,
-                        })
+                    if entry.hash == current.hash {
+                        entry.computed_at = now;
+                        entry.seen_at = now;
+                        continue;
+                    }
+                    let current_bytes = config.keeps_golden_copy().then_some(&*bytes);
+                    let mut action = emit(CheckerEvent::HashMismatch(entry.mismatch(
+                        region,
+                        &current,
+                        config,
+                        current_bytes,
+                    )));
+                    // repair replaces the action, a rescan is still done afterwards
+                    if action == CheckerAction::Rescan {
+                        rescan = true;
+                    }
+                    let mut keep_baseline = matches!(
+                        action,
+                        CheckerAction::KeepOldBaseline | CheckerAction::Rescan
+                    );
+                    if config.repair != RepairStrategy::Disabled
+                        && matches!(
+                            action,
+                            CheckerAction::Continue
+                                | CheckerAction::KeepOldBaseline
+                                | CheckerAction::Rescan
+                        )
+                    {
+                        keep_baseline = true;
+                        let region_info = region.clone();
+                        action = match unsafe { repair::repair(region, entry, config) } {
+                            Ok(bytes) => {
+                                entry.computed_at = now;
+                                emit(CheckerEvent::Repaired {
+                                    region: region_info,
+                                    bytes,
+                                })
+                            }
+                            Err(reason) => emit(CheckerEvent::RepairFailed {
+                                region: region_info,
+                                reason,
+                            }),
+                        };
+                    }
+                    if !keep_baseline {
+                        current.keep_repair_data(&bytes, config);
+                        *entry = current;
+                    }
+                    entry.seen_at = now;
+                    match action {
+                        CheckerAction::Stop => return Ok(()),
+                        CheckerAction::Rescan => rescan = true,
+                        _ => {}
                     }
-                    entry.hash = hash;
-                    entry.computed_at = now;
                 }
                 None => {
                     // add regions that are new
-                    region_hashes.insert(
-                        region,
-                        RegionHash {
-                            hash,
-                            computed_at: now,
-                        },
-                    );
+                    let mut actions = vec![emit(CheckerEvent::RegionAdded(region.clone()))];
+                    #[cfg(unix)]
+                    if config.verify_against_file {
+                        if let Ok(byte_diffs) = linux::compare_with_file(region, &bytes) {
+                            if !byte_diffs.is_empty() {
+                                actions.push(emit(CheckerEvent::FileMismatch {
+                                    region: region.clone(),
+                                    byte_diffs,
+                                }));
+                            }
+                        }
+                    }
+                    current.keep_repair_data(&bytes, config);
+                    drop(bytes);
+                    region_hashes.insert(region.clone(), current);
+                    for action in actions {
+                        match action {
+                            CheckerAction::Stop => return Ok(()),
+                            CheckerAction::Rescan => rescan = true,
+                            _ => {}
+                        }
+                    }
                 }
             }
         }
 
         // remove all regions that disappeared
-        region_hashes.retain(|_k, v| v.computed_at == now);
+        let mut actions = vec![];
+        region_hashes.retain(|k, v| {
+            let keep = v.seen_at == now;
+            if !keep {
+                actions.push(emit(CheckerEvent::RegionRemoved(k.clone())));
+            }
+            keep
+        });
+
+        #[cfg(unix)]
+        if config.check_got {
+            let in_use: Vec<_> = region_hashes
+                .keys()
+                .filter_map(|region| Some((region, control.use_module(region)?)))
+                .collect();
+            got.update(in_use.iter().map(|(region, _)| *region));
+            let events = unsafe { got.check(config) }?;
+            drop(in_use);
+            for event in events {
+                actions.push(emit(event));
+            }
+        }
+
+        let sealed_mismatches = sealed::check_all();
+        sealed_reported.retain(|region, _| {
+            sealed_mismatches
+                .iter()
+                .any(|mismatch| &mismatch.region == region)
+        });
+        for mismatch in sealed_mismatches {
+            if sealed_reported.insert(mismatch.region.clone(), mismatch.new_hash)
+                != Some(mismatch.new_hash)
+            {
+                actions.push(emit(CheckerEvent::HashMismatch(mismatch)));
+            }
+        }
+
+        let duration = now.elapsed();
+        actions.push(emit(CheckerEvent::ScanCompleted {
+            duration,
+            bytes: hashed_bytes,
+            regions: region_count,
+        }));
+        if duration > config.check_period {
+            actions.push(emit(CheckerEvent::ScanOverrun {
+                duration,
+                check_period: config.check_period,
+            }));
+        }
+        control.answer_baseline(|| Snapshot::new(config, region_hashes.clone()));
+
+        let mut stop = false;
+        for action in actions {
+            match action {
+                CheckerAction::Stop => stop = true,
+                CheckerAction::Rescan => rescan = true,
+                _ => {}
+            }
+        }
+        if stop {
+            return Ok(());
+        }
 
         // account for time spend execting when sleeping, only relevant if configured period is tiny
-        let sleep_duration = config.check_period - now.elapsed();
-        std::thread::sleep(sleep_duration);
+        sleep_duration = if rescan {
+            std::time::Duration::ZERO
+        } else {
+            config.check_period.saturating_sub(duration)
+        };
     }
+    Ok(())
 }
 
 /// Result of a [benchmark()](ProcessConsistencyChecker::benchmark) call
@@ -319,27 +1299,24 @@ pub struct BenchmarkResult {
     /// how much time was spent hashing memory regions
     hash_time: std::time::Duration,
     /// how many bytes were hashed in total
-    hashed_bytes: isize,
+    hashed_bytes: usize,
     /// which regions were hashed (including where they come from)
     regions: Vec<Region>,
 }
 
 fn run_benchmark(config: &CheckerConfig) -> Result<BenchmarkResult, Error> {
     let t0 = Instant::now();
-    let regions = get_all_regions(config.skip_libs, config.include_writable_code)?;
+    let regions = get_all_regions(config)?;
     let t1 = Instant::now();
     for region in &regions {
-        let _ = unsafe { region.compute_hash() };
+        unsafe { RegionHash::compute(region, config, t1) }?;
     }
     let t2 = Instant::now();
 
     Ok(BenchmarkResult {
         scan_time: t1 - t0,
         hash_time: t2 - t1,
-        hashed_bytes: regions
-            .iter()
-            .map(|r| unsafe { r.end.offset_from(r.start) })
-            .sum(),
+        hashed_bytes: regions.iter().map(|r| r.end - r.start).sum(),
         regions,
     })
 }
@@ -348,6 +1325,9 @@ fn run_benchmark(config: &CheckerConfig) -> Result<BenchmarkResult, Error> {
 mod tests {
     use super::*;
 
+    /// some tests modify our own code, tests inspecting it must not run concurrently
+    pub(crate) static CODE_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
     #[test]
     fn it_works() {
         println!(
@@ -355,4 +1335,288 @@ mod tests {
             ProcessConsistencyChecker::new().benchmark().unwrap()
         );
     }
+
+    #[test]
+    fn stop_interrupts_sleep() {
+        let handle = ProcessConsistencyChecker::new()
+            .check_period(std::time::Duration::from_secs(3600))
+            .spawn(|event| {
+                if let CheckerEvent::HashMismatch(error) = event {
+                    panic!("Memory Error: {:#?}", error)
+                }
+            })
+            .unwrap();
+        assert!(handle.is_running());
+        handle.pause();
+        assert!(handle.is_paused());
+        handle.resume();
+        let start = Instant::now();
+        handle.stop();
+        handle.join().unwrap();
+        assert!(start.elapsed() < std::time::Duration::from_secs(60));
+    }
+
+    #[test]
+    fn subscribers_receive_scan_events() {
+        let handle = ProcessConsistencyChecker::new()
+            .check_period(std::time::Duration::from_millis(10))
+            .spawn(|_| {})
+            .unwrap();
+        let events = handle.subscribe();
+        let completed = events
+            .iter()
+            .find(|event| matches!(event, CheckerEvent::ScanCompleted { .. }))
+            .unwrap();
+        handle.stop_and_join().unwrap();
+        match completed {
+            CheckerEvent::ScanCompleted { bytes, regions, .. } => {
+                assert!(bytes > 0);
+                assert!(regions > 0);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn registered_regions_are_checked() {
+        let handle = ProcessConsistencyChecker::new()
+            .skip_libs(true)
+            .check_period(std::time::Duration::from_millis(10))
+            .spawn(|_| {})
+            .unwrap();
+        let events = handle.subscribe();
+        let mut buffer = vec![0u8; 100].into_boxed_slice();
+        let id = unsafe { handle.register_raw_region("buffer", buffer.as_ptr(), buffer.len(), ()) };
+        let is_buffer =
+            |region: &Region| region.source == "buffer" && region.segment == Segment::Custom;
+        events
+            .iter()
+            .find(|event| matches!(event, CheckerEvent::RegionAdded(region) if is_buffer(region)))
+            .unwrap();
+
+        unsafe { std::ptr::write_volatile(&mut buffer[42], 1) };
+        let mismatch = events
+            .iter()
+            .find_map(|event| match event {
+                CheckerEvent::HashMismatch(error) if is_buffer(&error.region) => Some(error),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(mismatch.region.end - mismatch.region.start, 100);
+
+        assert!(handle.unregister_region(id));
+        assert!(!handle.unregister_region(id));
+        events
+            .iter()
+            .find(|event| matches!(event, CheckerEvent::RegionRemoved(region) if is_buffer(region)))
+            .unwrap();
+        handle.stop_and_join().unwrap();
+    }
+
+    #[test]
+    fn rescan_keeps_old_baseline() {
+        let mock = std::sync::Arc::new(MockRegionProvider::new());
+        let region = mock.add("table", vec![0u8; 64]);
+        let mut rounds = 0;
+        let mut mismatches = vec![];
+        ProcessConsistencyChecker::new()
+            .system_regions(false)
+            .check_period(std::time::Duration::ZERO)
+            .region_provider(mock.clone())
+            .run(|event| match event {
+                CheckerEvent::ScanCompleted { .. } => {
+                    rounds += 1;
+                    if rounds == 1 {
+                        mock.write(&region, 3, &[0xff]);
+                    }
+                    match rounds {
+                        3 => CheckerAction::Stop,
+                        _ => CheckerAction::Continue,
+                    }
+                }
+                CheckerEvent::HashMismatch(error) => {
+                    mismatches.push(error.clone());
+                    CheckerAction::Rescan
+                }
+                _ => CheckerAction::Continue,
+            })
+            .unwrap();
+        // the change is still reported after the rescan
+        assert_eq!(mismatches.len(), 2);
+        assert_eq!(mismatches[0].old_hash, mismatches[1].old_hash);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unmodified_data_matches_file() {
+        let _lock = CODE_LOCK.lock().unwrap();
+        let mut mismatches = vec![];
+        let mut segments = std::collections::HashSet::new();
+        ProcessConsistencyChecker::new()
+            .include_readonly_data(true)
+            .verify_against_file(true)
+            .run(|event| match event {
+                CheckerEvent::RegionAdded(region) => {
+                    segments.insert(region.segment);
+                    CheckerAction::Continue
+                }
+                CheckerEvent::FileMismatch { region, .. } => {
+                    mismatches.push(region.clone());
+                    CheckerAction::Continue
+                }
+                CheckerEvent::ScanCompleted { .. } => CheckerAction::Stop,
+                _ => CheckerAction::Continue,
+            })
+            .unwrap();
+        assert!(segments.contains(&Segment::ReadOnlyData) && segments.contains(&Segment::Relro));
+        assert_eq!(mismatches, vec![]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unload_guard_dropped_during_round() {
+        let exe = std::env::current_exe().unwrap();
+        let regions = get_all_regions(&CheckerConfig::default()).unwrap();
+        let program = regions
+            .iter()
+            .position(|region| std::path::Path::new(&region.source) == exe)
+            .unwrap();
+        // a module the checker only gets to after the program
+        let library = regions[program..]
+            .iter()
+            .find(|region| region.file.is_some() && std::path::Path::new(&region.source) != exe)
+            .unwrap()
+            .source
+            .clone();
+        let (paused_sender, paused) = std::sync::mpsc::channel();
+        let (resume, resumed) = std::sync::mpsc::channel();
+        let mut first = true;
+        let handle = ProcessConsistencyChecker::new()
+            .check_period(std::time::Duration::from_millis(10))
+            .spawn(move |event| {
+                if let CheckerEvent::RegionAdded(region) = event {
+                    if first && std::path::Path::new(&region.source) == exe {
+                        first = false;
+                        paused_sender.send(()).unwrap();
+                        resumed.recv().unwrap();
+                    }
+                }
+            })
+            .unwrap();
+
+        // unload while the checker is in the middle of its first round
+        paused.recv().unwrap();
+        let events = handle.subscribe();
+        drop(handle.unload_guard(&library));
+        resume.send(()).unwrap();
+        for event in events.iter() {
+            match event {
+                CheckerEvent::RegionAdded(region) => assert_ne!(region.source, library),
+                CheckerEvent::ScanCompleted { .. } => break,
+                _ => {}
+            }
+        }
+        // the module is still loaded, so the next discovery finds it again
+        events
+            .iter()
+            .find(|event| matches!(event, CheckerEvent::RegionAdded(region) if region.source == library))
+            .unwrap();
+        handle.stop_and_join().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unload_guard_removes_module() {
+        let exe = std::env::current_exe().unwrap();
+        let library = get_all_regions(&CheckerConfig::default())
+            .unwrap()
+            .into_iter()
+            .find(|region| region.file.is_some() && std::path::Path::new(&region.source) != exe)
+            .unwrap()
+            .source;
+        let handle = ProcessConsistencyChecker::new()
+            .check_period(std::time::Duration::from_millis(10))
+            .spawn(|_| {})
+            .unwrap();
+        let events = handle.subscribe();
+        events
+            .iter()
+            .find(|event| matches!(event, CheckerEvent::ScanCompleted { .. }))
+            .unwrap();
+
+        let guard = handle.unload_guard(&library);
+        events
+            .iter()
+            .find(|event| matches!(event, CheckerEvent::RegionRemoved(region) if region.source == library))
+            .unwrap();
+        // the module stays out of the checks while the guard is alive
+        let is_library = |event: &CheckerEvent| match event {
+            CheckerEvent::RegionAdded(region) => region.source == library,
+            _ => false,
+        };
+        let rounds = events
+            .iter()
+            .filter(|event| {
+                matches!(event, CheckerEvent::ScanCompleted { .. }) || is_library(event)
+            })
+            .take(2)
+            .collect::<Vec<_>>();
+        assert!(!rounds.iter().any(is_library));
+
+        drop(guard);
+        events.iter().find(is_library).unwrap();
+        handle.stop_and_join().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn loading_a_library_triggers_rediscovery() {
+        let handle = ProcessConsistencyChecker::new()
+            .check_period(std::time::Duration::from_millis(10))
+            .rediscovery_period(std::time::Duration::from_secs(3600))
+            .spawn(|_| {})
+            .unwrap();
+        let events = handle.subscribe();
+        let initial = events
+            .iter()
+            .map_while(|event| match event {
+                CheckerEvent::RegionAdded(region) => Some(region),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        // libm comes with libc but isn't linked into the tests, libz is common too
+        let Some((name, library)) = ["libm.so.6", "libz.so.1"].into_iter().find_map(|name| {
+            if initial.iter().any(|region| region.source.contains(name)) {
+                return None;
+            }
+            let library = std::ffi::CString::new(name).unwrap();
+            let library = unsafe { libc::dlopen(library.as_ptr(), libc::RTLD_NOW) };
+            (!library.is_null()).then_some((name, library))
+        }) else {
+            eprintln!("skipped: no library is installed that isn't loaded already");
+            handle.stop_and_join().unwrap();
+            return;
+        };
+        let is_library = |region: &Region| region.source.contains(name);
+        events
+            .iter()
+            .find(|event| matches!(event, CheckerEvent::RegionAdded(region) if is_library(region)))
+            .unwrap();
+        let guard = handle.unload_guard(name);
+        events
+            .iter()
+            .find(
+                |event| matches!(event, CheckerEvent::RegionRemoved(region) if is_library(region)),
+            )
+            .unwrap();
+        unsafe { libc::dlclose(library) };
+        drop(guard);
+        handle.stop_and_join().unwrap();
+    }
+
+    #[test]
+    fn events_are_sendable() {
+        fn assert_send<T: Send + 'static>() {}
+        assert_send::<CheckerEvent>();
+    }
 }
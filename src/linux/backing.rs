@@ -0,0 +1,241 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    ops::Range,
+    os::unix::{fs::FileExt, fs::MetadataExt, io::AsRawFd},
+    sync::Mutex,
+};
+
+use object::{
+    elf,
+    read::{
+        elf::{ElfFile, FileHeader, ProgramHeader},
+        ReadCache,
+    },
+    Endianness, Object, ObjectSegment,
+};
+
+use crate::{
+    error::unix_get_last_error,
+    golden::{byte_diffs, ByteDiff},
+    CheckerConfig, MappedFile, Region, Segment,
+};
+
+/// open a mapped file, making sure it's still the same file on disk
+pub(super) fn open_mapped_file(path: &str, device: (u32, u32), inode: u64) -> Result<File, String> {
+    let file = File::open(path).map_err(|e| format!("unable to open {}: {}", path, e))?;
+    let metadata = file
+        .metadata()
+        .map_err(|e| format!("unable to stat {}: {}", path, e))?;
+    let actual_device = (libc::major(metadata.dev()), libc::minor(metadata.dev()));
+    if actual_device != device || metadata.ino() != inode {
+        return Err(format!("{} on disk is not the file that was mapped", path));
+    }
+    Ok(file)
+}
+
+/// open the file a region was mapped from, making sure it's still the same file
+fn open_backing_file(region: &Region) -> Result<(File, &MappedFile), String> {
+    let backing = region
+        .file
+        .as_ref()
+        .ok_or("region is not mapped from a file")?;
+    let file = open_mapped_file(&region.source, backing.device, backing.inode)?;
+    Ok((file, backing))
+}
+
+/// metadata of a mapped ELF file
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ElfInfo {
+    pub(crate) build_id: Option<Vec<u8>>,
+    /// file offsets of the data the loader makes read-only after relocation (PT_GNU_RELRO)
+    pub(crate) relro: Option<Range<u64>>,
+}
+
+/// like [read_elf_info], but every file is only read once. This keeps discovery from opening files over and over,
+/// which might not be allowed anymore once the process sandboxed itself
+pub(crate) fn cached_elf_info(path: &str, device: (u32, u32), inode: u64) -> ElfInfo {
+    /// path, device and inode
+    type FileKey = (String, (u32, u32), u64);
+    static ELF_INFOS: Mutex<BTreeMap<FileKey, ElfInfo>> = Mutex::new(BTreeMap::new());
+    ELF_INFOS
+        .lock()
+        .unwrap()
+        .entry((path.to_owned(), device, inode))
+        .or_insert_with(|| read_elf_info(path, device, inode))
+        .clone()
+}
+
+/// read the build-id and RELRO segment of a mapped file. Empty if it's not an ELF file or was replaced on disk
+fn read_elf_info(path: &str, device: (u32, u32), inode: u64) -> ElfInfo {
+    let Ok(file) = open_mapped_file(path, device, inode) else {
+        return ElfInfo::default();
+    };
+    let cache = ReadCache::new(&file);
+    let build_id = object::File::parse(&cache)
+        .ok()
+        .and_then(|elf| elf.build_id().ok().flatten().map(<[u8]>::to_vec));
+    let relro = match object::FileKind::parse(&cache) {
+        Ok(object::FileKind::Elf32) => relro_range::<elf::FileHeader32<Endianness>>(&cache),
+        Ok(object::FileKind::Elf64) => relro_range::<elf::FileHeader64<Endianness>>(&cache),
+        _ => None,
+    };
+    ElfInfo { build_id, relro }
+}
+
+fn relro_range<Elf: FileHeader<Endian = Endianness>>(
+    cache: &ReadCache<&File>,
+) -> Option<Range<u64>> {
+    let elf = ElfFile::<Elf, _>::parse(cache).ok()?;
+    let endian = elf.endian();
+    elf.elf_program_headers()
+        .iter()
+        .find(|header| header.p_type(endian) == elf::PT_GNU_RELRO)
+        .map(|header| {
+            let offset: u64 = header.p_offset(endian).into();
+            offset..offset + Into::<u64>::into(header.p_filesz(endian))
+        })
+}
+
+/// read the part of the file that is mapped to the region
+fn read_backing_file(
+    file: &File,
+    backing: &MappedFile,
+    region: &Region,
+) -> Result<Vec<u8>, String> {
+    // like mmap, treat everything after the end of the file as zeros
+    let len = region.end - region.start;
+    let mut contents = vec![0; len];
+    let mut read = 0;
+    while read < len {
+        match file.read_at(&mut contents[read..], backing.offset + read as u64) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(format!("unable to read {}: {}", region.source, e)),
+        }
+    }
+    Ok(contents)
+}
+
+/// file offsets of all bytes the dynamic loader patches when loading the file. In code regions these only exist
+/// for objects with text relocations
+fn relocated_ranges(file: &File) -> Vec<Range<u64>> {
+    let cache = ReadCache::new(file);
+    let Ok(elf) = object::File::parse(&cache) else {
+        return vec![];
+    };
+    let Some(relocations) = elf.dynamic_relocations() else {
+        return vec![];
+    };
+    let pointer_size = if elf.is_64() { 8 } else { 4 };
+    relocations
+        .filter_map(|(address, relocation)| {
+            // translate the virtual address the loader writes to into a file offset
+            let segment = elf.segments().find(|segment| {
+                (segment.address()..segment.address() + segment.file_range().1).contains(&address)
+            })?;
+            let offset = address - segment.address() + segment.file_range().0;
+            let size = match relocation.size() {
+                0 => pointer_size,
+                bits => u64::from(bits) / 8,
+            };
+            Some(offset..offset + size)
+        })
+        .collect()
+}
+
+/// compare the contents of a file backed region to the file on disk. Returns every byte that differs, except for
+/// bytes changed by text relocations. `old` is the value on disk, `new` the value in memory.
+///
+/// RELRO can't be compared, the loader writes pointers all over it, and into `.dynamic` too
+pub(crate) fn compare_with_file(region: &Region, current: &[u8]) -> Result<Vec<ByteDiff>, String> {
+    if region.segment == Segment::Relro {
+        return Err("relocated data differs from the file".into());
+    }
+    let (file, backing) = open_backing_file(region)?;
+    let contents = read_backing_file(&file, backing, region)?;
+    let mut diffs = byte_diffs(&contents, current);
+    if !diffs.is_empty() {
+        let relocated = relocated_ranges(&file);
+        diffs.retain(|diff| {
+            let offset = backing.offset + diff.offset as u64;
+            !relocated.iter().any(|range| range.contains(&offset))
+        });
+    }
+    Ok(diffs)
+}
+
+/// replace the changed pages of a file backed region with a fresh private mapping of the file it was loaded from.
+///
+/// Refuses to touch memory if the file on disk is not the one that was mapped, or if `is_expected` rejects the
+/// file's contents. Returns how many bytes were remapped
+///
+/// # SAFETY
+/// same as [Region::read]. Any other thread executing the remapped range at the same time might crash
+pub(crate) unsafe fn remap_from_file(
+    region: &Region,
+    config: &CheckerConfig,
+    is_expected: impl Fn(&[u8]) -> bool,
+) -> Result<usize, String> {
+    let (file, backing) = open_backing_file(region)?;
+    let contents = read_backing_file(&file, backing, region)?;
+    let len = contents.len();
+    if !is_expected(&contents) {
+        return Err(format!(
+            "{} on disk doesn't match the baseline",
+            region.source
+        ));
+    }
+
+    let current = unsafe { region.read(config) }
+        .map_err(|e| e.to_string())?
+        .ok_or("region was unmapped")?;
+    let diffs = byte_diffs(&contents, &current);
+    drop(current);
+    let (first, last) = match (diffs.first(), diffs.last()) {
+        (Some(first), Some(last)) => (first.offset, last.offset),
+        // memory already changed back
+        _ => return Ok(0),
+    };
+    // regions found with dl_iterate_phdr don't have to start at a page boundary. Their file offset is congruent to
+    // their address modulo the page size though, so the pages around them can be mapped from the file as well
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+    let page_start = (region.start + first) & !(page_size - 1);
+    let page_end = ((region.start + last + page_size) & !(page_size - 1)).min(region.start + len);
+    let remap_len = page_end - page_start;
+    let mapped = unsafe {
+        libc::mmap(
+            page_start as *mut _,
+            remap_len,
+            region.permissions.prot(),
+            libc::MAP_PRIVATE | libc::MAP_FIXED,
+            file.as_raw_fd(),
+            (backing.offset + page_start as u64 - region.start as u64) as libc::off_t,
+        )
+    };
+    if mapped == libc::MAP_FAILED {
+        return Err(unix_get_last_error("mmap").to_string());
+    }
+    Ok(remap_len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn own_code_matches_file() {
+        let _lock = crate::tests::CODE_LOCK.lock().unwrap();
+        let regions = crate::get_all_regions(&crate::CheckerConfig {
+            skip_libs: true,
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(!regions.is_empty());
+        for region in regions {
+            let current = unsafe { region.as_slice() };
+            assert_eq!(compare_with_file(&region, current), Ok(vec![]));
+        }
+    }
+}
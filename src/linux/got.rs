@@ -0,0 +1,372 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::{CStr, CString},
+    ops::Range,
+    os::unix::fs::FileExt,
+};
+
+use object::{
+    elf, read::ReadCache, Architecture, Object, ObjectSegment, ObjectSymbol, ObjectSymbolTable,
+    RelocationFlags, RelocationTarget, SymbolKind,
+};
+
+use super::{backing::open_mapped_file, maps, phdr};
+use crate::{error::Error, CheckerConfig, CheckerEvent, GotEntry, Region};
+
+/// Verifies the GOT entries of all loaded modules against the dynamic linker's symbol resolution
+#[derive(Debug, Default)]
+pub(crate) struct GotChecker {
+    /// keyed by device, inode and load base of the module
+    tables: HashMap<((u32, u32), u64, usize), GotTable>,
+}
+
+#[derive(Debug)]
+struct GotTable {
+    module: String,
+    slots: Vec<GotSlot>,
+}
+
+/// why an entry is reported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Problem {
+    /// points to other code than the symbol
+    Mismatch,
+    /// doesn't point to code at all
+    OutsideCode,
+}
+
+#[derive(Debug)]
+struct GotSlot {
+    /// where the entry is in memory
+    address: usize,
+    symbol: CString,
+    /// the value before the symbol is resolved, for lazily bound entries
+    unresolved: Option<usize>,
+    /// the value that was last reported, to report every bad value only once
+    reported: Option<usize>,
+}
+
+impl GotChecker {
+    /// parse the GOT of every module that has a region in `regions`, and forget modules that are gone
+    pub(crate) fn update<'a>(&mut self, regions: impl Iterator<Item = &'a Region>) {
+        let mut seen = HashSet::new();
+        for region in regions {
+            let Some(file) = &region.file else {
+                continue;
+            };
+            let key = (file.device, file.inode, file.module_base);
+            if seen.insert(key) && !self.tables.contains_key(&key) {
+                let mut slots = read_got(region).unwrap_or_default();
+                // entries are read directly from memory, so make sure they are where the loader relocates. A wrong load
+                // base would point them anywhere
+                let relocated = phdr::relocated_ranges(file.module_base).unwrap_or_default();
+                let is_relocated = |slot: &GotSlot| {
+                    relocated.iter().any(|range| {
+                        range.start <= slot.address
+                            && slot.address + std::mem::size_of::<usize>() <= range.end
+                    })
+                };
+                if !slots.iter().all(is_relocated) {
+                    slots.clear();
+                }
+                self.tables.insert(
+                    key,
+                    GotTable {
+                        module: region.source.clone(),
+                        slots,
+                    },
+                );
+            }
+        }
+        self.tables.retain(|key, _| seen.contains(key));
+    }
+
+    /// check every entry, returning an event for every entry that changed to a bad value since the last check
+    ///
+    /// # SAFETY
+    /// the modules passed to [update](Self::update) have to be still loaded, unless safe reads are configured
+    pub(crate) unsafe fn check(
+        &mut self,
+        config: &CheckerConfig,
+    ) -> Result<Vec<CheckerEvent>, Error> {
+        let code = executable_ranges();
+        let mut events = vec![];
+        for table in self.tables.values_mut() {
+            // skip modules that were unmapped meanwhile
+            let Some(values) = (unsafe { read_slots(&table.slots, config) })? else {
+                continue;
+            };
+            for (slot, value) in table.slots.iter_mut().zip(values) {
+                let problem = unsafe { slot.verify(value, &code) };
+                let Some(problem) = problem else {
+                    slot.reported = None;
+                    continue;
+                };
+                if slot.reported == Some(value) {
+                    continue;
+                }
+                slot.reported = Some(value);
+                let entry = GotEntry {
+                    module: table.module.clone(),
+                    address: slot.address,
+                    symbol: slot.symbol.to_string_lossy().into_owned(),
+                    value,
+                    expected: resolve(&slot.symbol),
+                };
+                events.push(match problem {
+                    Problem::Mismatch => CheckerEvent::GotMismatch(entry),
+                    Problem::OutsideCode => CheckerEvent::GotEntryOutsideCode(entry),
+                });
+            }
+        }
+        Ok(events)
+    }
+}
+
+/// the current values of GOT entries, read with [safe_reads](crate::ProcessConsistencyChecker::safe_reads) if
+/// configured. Ok(None) if the entries were unmapped, which can only be detected with safe reads
+///
+/// # SAFETY
+/// same as [GotChecker::check]
+unsafe fn read_slots(
+    slots: &[GotSlot],
+    config: &CheckerConfig,
+) -> Result<Option<Vec<usize>>, Error> {
+    if !config.safe_reads {
+        return Ok(Some(
+            slots
+                .iter()
+                .map(|slot| unsafe { std::ptr::read_unaligned(slot.address as *const usize) })
+                .collect(),
+        ));
+    }
+    const SIZE: usize = std::mem::size_of::<usize>();
+    let (Some(start), Some(end)) = (
+        slots.iter().map(|slot| slot.address).min(),
+        slots.iter().map(|slot| slot.address + SIZE).max(),
+    ) else {
+        return Ok(Some(vec![]));
+    };
+    // the entries are close together, so read them all at once
+    let Some(bytes) = super::read_memory(start, end - start)? else {
+        return Ok(None);
+    };
+    Ok(Some(
+        slots
+            .iter()
+            .map(|slot| {
+                let offset = slot.address - start;
+                usize::from_ne_bytes(bytes[offset..offset + SIZE].try_into().unwrap())
+            })
+            .collect(),
+    ))
+}
+
+impl GotSlot {
+    /// None if the value is fine
+    ///
+    /// # SAFETY
+    /// only calls into the dynamic linker, which is safe as long as `value` isn't dereferenced
+    unsafe fn verify(&self, value: usize, code: &[Range<usize>]) -> Option<Problem> {
+        // unresolved weak symbols and lazy bindings that weren't called yet
+        if value == 0 || Some(value) == self.unresolved {
+            return None;
+        }
+        if !code.iter().any(|range| range.contains(&value)) {
+            return Some(Problem::OutsideCode);
+        }
+        if resolve(&self.symbol) == Some(value) {
+            return None;
+        }
+        // symbols can legitimately be bound to another definition than the global one, e.g. an older symbol
+        // version or a library's own copy. Accept that as long as the target has the same name
+        if symbol_at(value).as_deref() == Some(self.symbol.as_c_str()) {
+            return None;
+        }
+        Some(Problem::Mismatch)
+    }
+}
+
+/// where the dynamic linker resolves a symbol to
+fn resolve(symbol: &CStr) -> Option<usize> {
+    let address = unsafe { libc::dlsym(libc::RTLD_DEFAULT, symbol.as_ptr()) };
+    (!address.is_null()).then_some(address as usize)
+}
+
+/// name of the symbol containing an address
+fn symbol_at(address: usize) -> Option<CString> {
+    let mut info: libc::Dl_info = unsafe { std::mem::zeroed() };
+    if unsafe { libc::dladdr(address as *const _, &mut info) } == 0 || info.dli_sname.is_null() {
+        return None;
+    }
+    Some(unsafe { CStr::from_ptr(info.dli_sname) }.to_owned())
+}
+
+/// all executable memory of the process
+fn executable_ranges() -> Vec<Range<usize>> {
+    match maps::read_self() {
+        Ok(mappings) => mappings
+            .into_iter()
+            .filter(|mapping| mapping.permissions.execute)
+            .map(|mapping| mapping.start..mapping.end)
+            .collect(),
+        // without procfs only the code of loaded objects is known
+        Err(_) => phdr::executable_ranges(),
+    }
+}
+
+/// relocation types of GOT entries for functions: (JUMP_SLOT, GLOB_DAT)
+fn got_relocation_types(architecture: Architecture) -> Option<(u32, Option<u32>)> {
+    Some(match architecture {
+        Architecture::X86_64 => (elf::R_X86_64_JUMP_SLOT, Some(elf::R_X86_64_GLOB_DAT)),
+        Architecture::I386 => (elf::R_386_JMP_SLOT, Some(elf::R_386_GLOB_DAT)),
+        Architecture::Aarch64 => (elf::R_AARCH64_JUMP_SLOT, Some(elf::R_AARCH64_GLOB_DAT)),
+        Architecture::Arm => (elf::R_ARM_JUMP_SLOT, Some(elf::R_ARM_GLOB_DAT)),
+        Architecture::Riscv64 | Architecture::Riscv32 => (elf::R_RISCV_JUMP_SLOT, None),
+        _ => return None,
+    })
+}
+
+/// find the GOT entries of the module a region belongs to, using the dynamic relocations of its file
+fn read_got(region: &Region) -> Option<Vec<GotSlot>> {
+    let file = region.file.as_ref()?;
+    let opened = open_mapped_file(&region.source, file.device, file.inode).ok()?;
+    let cache = ReadCache::new(&opened);
+    let elf = object::File::parse(&cache).ok()?;
+    let (jump_slot, glob_dat) = got_relocation_types(elf.architecture())?;
+    let symbols = elf.dynamic_symbol_table()?;
+    let first_segment = elf.segments().find(|segment| segment.file_range().0 == 0)?;
+    let bias = file
+        .module_base
+        .wrapping_sub(first_segment.address() as usize);
+    let pointer_size = if elf.is_64() { 8 } else { 4 };
+
+    let mut slots = vec![];
+    for (address, relocation) in elf.dynamic_relocations()? {
+        let RelocationFlags::Elf { r_type } = relocation.flags() else {
+            continue;
+        };
+        let is_jump_slot = r_type == jump_slot;
+        if !is_jump_slot && Some(r_type) != glob_dat {
+            continue;
+        }
+        let RelocationTarget::Symbol(index) = relocation.target() else {
+            continue;
+        };
+        let Ok(symbol) = symbols.symbol_by_index(index) else {
+            continue;
+        };
+        // GLOB_DAT is also used for data, which doesn't point to code
+        if !is_jump_slot && symbol.kind() != SymbolKind::Text {
+            continue;
+        }
+        let Some(name) = symbol.name().ok().and_then(|name| CString::new(name).ok()) else {
+            continue;
+        };
+        // until a lazy binding is resolved, the entry contains the relocated value from the file
+        let unresolved = is_jump_slot
+            .then(|| read_pointer(&elf, &opened, address, pointer_size))
+            .flatten()
+            .map(|value| bias.wrapping_add(value));
+        slots.push(GotSlot {
+            address: bias.wrapping_add(address as usize),
+            symbol: name,
+            unresolved,
+            reported: None,
+        });
+    }
+    Some(slots)
+}
+
+/// read a pointer at a virtual address from the file
+fn read_pointer<'data>(
+    elf: &impl Object<'data>,
+    file: &std::fs::File,
+    address: u64,
+    pointer_size: usize,
+) -> Option<usize> {
+    let segment = elf.segments().find(|segment| {
+        (segment.address()..segment.address() + segment.file_range().1).contains(&address)
+    })?;
+    let offset = address - segment.address() + segment.file_range().0;
+    let mut bytes = [0; 8];
+    let bytes = &mut bytes[..pointer_size];
+    file.read_exact_at(bytes, offset).ok()?;
+    if elf.is_little_endian() {
+        bytes.reverse();
+    }
+    Some(
+        bytes
+            .iter()
+            .fold(0, |value, &byte| value << 8 | byte as usize),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn own_got_is_intact() {
+        let regions = crate::get_all_regions(&crate::CheckerConfig::default()).unwrap();
+        let mut checker = GotChecker::default();
+        checker.update(regions.iter());
+        assert!(checker.tables.values().any(|table| !table.slots.is_empty()));
+        let config = crate::CheckerConfig::default();
+        assert_eq!(unsafe { checker.check(&config) }.unwrap(), vec![]);
+        let config = crate::CheckerConfig {
+            safe_reads: true,
+            ..Default::default()
+        };
+        assert_eq!(unsafe { checker.check(&config) }.unwrap(), vec![]);
+    }
+
+    #[test]
+    fn skips_modules_with_wrong_base() {
+        let address = libc::getpid as *const () as usize;
+        let mut region = crate::get_all_regions(&crate::CheckerConfig::default())
+            .unwrap()
+            .into_iter()
+            .find(|region| (region.start..region.end).contains(&address))
+            .unwrap();
+        let mut checker = GotChecker::default();
+        checker.update(std::iter::once(&region));
+        assert!(checker.tables.values().all(|table| !table.slots.is_empty()));
+
+        // e.g. the base of another mapping of the same file
+        let file = region.file.as_mut().unwrap();
+        file.module_base -= 0x10_0000;
+        let mut checker = GotChecker::default();
+        checker.update(std::iter::once(&region));
+        assert!(checker.tables.values().all(|table| table.slots.is_empty()));
+        assert_eq!(
+            unsafe { checker.check(&Default::default()) }.unwrap(),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn reports_redirected_entries() {
+        let symbol = CString::new("strlen").unwrap();
+        let strlen = resolve(&symbol).unwrap();
+        let slot = GotSlot {
+            address: 0,
+            symbol,
+            unresolved: None,
+            reported: None,
+        };
+        let code = executable_ranges();
+        let other_function = reports_redirected_entries as fn() as usize;
+        let data = &code as *const _ as usize;
+
+        assert_eq!(unsafe { slot.verify(strlen, &code) }, None);
+        assert_eq!(
+            unsafe { slot.verify(other_function, &code) },
+            Some(Problem::Mismatch)
+        );
+        assert_eq!(
+            unsafe { slot.verify(data, &code) },
+            Some(Problem::OutsideCode)
+        );
+    }
+}
@@ -0,0 +1,336 @@
+//! Parser for `/proc/<pid>/maps`
+//!
+//! ```rust
+//!   use process_consistency::maps::{self, MappingKind};
+//!   for mapping in maps::read_self().unwrap() {
+//!       if mapping.permissions.execute && mapping.kind == MappingKind::Anonymous {
+//!           println!("anonymous code at {:x}-{:x}", mapping.start, mapping.end);
+//!       }
+//!   }
+//! ```
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    os::unix::fs::FileExt,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use crate::{error::Error, Permissions, UnbackedMappings};
+
+/// A single line of a maps file
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Mapping {
+    /// first address of the mapping
+    pub start: usize,
+    /// first address after the mapping
+    pub end: usize,
+    pub permissions: Permissions,
+    /// offset into the mapped file, 0 for anonymous mappings
+    pub offset: u64,
+    /// (major, minor) device number of the mapped file
+    pub device: (u32, u32),
+    /// inode of the mapped file, 0 for anonymous mappings
+    pub inode: u64,
+    /// the pathname column as printed by the kernel, including a ` (deleted)` suffix
+    pub pathname: String,
+    pub kind: MappingKind,
+}
+
+/// What a [Mapping] refers to
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MappingKind {
+    /// a file on disk
+    File {
+        path: PathBuf,
+        /// the file was deleted or replaced after it was mapped
+        deleted: bool,
+    },
+    /// memory without a file, e.g. from a JIT or `mmap(MAP_ANONYMOUS)`. Includes memory named with
+    /// `prctl(PR_SET_VMA_ANON_NAME)`, shown as `[anon:name]` or `[anon_shmem:name]` in [pathname](Mapping::pathname)
+    Anonymous,
+    /// a mapping with a name in brackets like `[vdso]` or `[heap]`. Contains the name without brackets
+    Pseudo(String),
+}
+
+impl UnbackedMappings {
+    pub(crate) fn includes(&self, kind: &MappingKind) -> bool {
+        match kind {
+            MappingKind::File { .. } => true,
+            MappingKind::Pseudo(_) => *self != Self::Skip,
+            MappingKind::Anonymous => *self == Self::All,
+        }
+    }
+}
+
+const DELETED_SUFFIX: &str = " (deleted)";
+
+impl Mapping {
+    /// parse a single line of a maps file
+    pub fn parse(line: &str) -> Option<Self> {
+        let (range, rest) = next_field(line)?;
+        let (perms, rest) = next_field(rest)?;
+        let (offset, rest) = next_field(rest)?;
+        let (device, rest) = next_field(rest)?;
+        let (inode, rest) = next_field(rest)?;
+        // the pathname is padded with spaces, but may contain spaces itself
+        let pathname = rest.trim_start_matches(' ');
+
+        let (start, end) = range.split_once('-')?;
+        let (major, minor) = device.split_once(':')?;
+        let perms = perms.as_bytes();
+        if perms.len() != 4 {
+            return None;
+        }
+
+        Some(Self {
+            start: usize::from_str_radix(start, 16).ok()?,
+            end: usize::from_str_radix(end, 16).ok()?,
+            permissions: Permissions {
+                read: perms[0] == b'r',
+                write: perms[1] == b'w',
+                execute: perms[2] == b'x',
+                shared: perms[3] == b's',
+            },
+            offset: u64::from_str_radix(offset, 16).ok()?,
+            device: (
+                u32::from_str_radix(major, 16).ok()?,
+                u32::from_str_radix(minor, 16).ok()?,
+            ),
+            inode: inode.parse().ok()?,
+            pathname: pathname.to_owned(),
+            kind: MappingKind::of(pathname),
+        })
+    }
+
+    /// the mapped file, if this is a file mapping
+    pub fn path(&self) -> Option<&Path> {
+        match &self.kind {
+            MappingKind::File { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+}
+
+impl MappingKind {
+    fn of(pathname: &str) -> Self {
+        if pathname.is_empty() {
+            Self::Anonymous
+        } else if let Some(name) = pathname
+            .strip_prefix('[')
+            .and_then(|name| name.strip_suffix(']'))
+        {
+            // names given to anonymous memory by the program, not by the kernel
+            if name.starts_with("anon:") || name.starts_with("anon_shmem:") {
+                Self::Anonymous
+            } else {
+                Self::Pseudo(name.to_owned())
+            }
+        } else if let Some(path) = pathname.strip_suffix(DELETED_SUFFIX) {
+            Self::File {
+                path: path.into(),
+                deleted: true,
+            }
+        } else {
+            Self::File {
+                path: pathname.into(),
+                deleted: false,
+            }
+        }
+    }
+}
+
+fn next_field(s: &str) -> Option<(&str, &str)> {
+    let s = s.trim_start_matches(' ');
+    let end = s.find(' ').unwrap_or(s.len());
+    (end > 0).then(|| (&s[..end], &s[end..]))
+}
+
+const SELF_MAPS: &str = "/proc/self/maps";
+
+/// `/proc/self/maps`, kept open so it can be reread after a sandbox prevents opening files. The file shows the
+/// memory of the process that opened it, so it's stored with that process's pid
+static SELF_MAPS_FILE: Mutex<Option<(libc::pid_t, Arc<File>)>> = Mutex::new(None);
+
+/// read and parse a maps file, e.g. `/proc/1234/maps`
+pub fn read(path: &Path) -> Result<Vec<Mapping>, Error> {
+    parse_all(open(path)?, path)
+}
+
+/// read and parse `/proc/self/maps`
+pub fn read_self() -> Result<Vec<Mapping>, Error> {
+    let pid = unsafe { libc::getpid() };
+    let mut kept = SELF_MAPS_FILE.lock().unwrap();
+    let file = match &*kept {
+        Some((opened_by, file)) if *opened_by == pid => Some(file.clone()),
+        // a forked child inherits the file of its parent, which still shows the memory of the parent
+        Some(_) => {
+            let file = Arc::new(open(Path::new(SELF_MAPS))?);
+            *kept = Some((pid, file.clone()));
+            Some(file)
+        }
+        None => None,
+    };
+    // don't hold the lock while reading, a fork meanwhile would leave it locked in the child
+    drop(kept);
+    match file {
+        // procfs generates the contents anew when reading from the start
+        Some(file) => parse_all(
+            ReadAt {
+                file: &file,
+                offset: 0,
+            },
+            Path::new(SELF_MAPS),
+        ),
+        None => read(Path::new(SELF_MAPS)),
+    }
+}
+
+/// open `/proc/self/maps` once per process, and reread it with `pread` from then on
+pub(crate) fn keep_self_open() -> Result<(), Error> {
+    let pid = unsafe { libc::getpid() };
+    let mut kept = SELF_MAPS_FILE.lock().unwrap();
+    if !matches!(&*kept, Some((opened_by, _)) if *opened_by == pid) {
+        *kept = Some((pid, Arc::new(open(Path::new(SELF_MAPS))?)));
+    }
+    Ok(())
+}
+
+fn open(path: &Path) -> Result<File, Error> {
+    File::open(path).map_err(|e| Error::ProcFsUnavailableError {
+        source: e,
+        path: path.to_owned(),
+    })
+}
+
+/// reads a file with `pread`, which doesn't need the file's position to be reset between reads
+struct ReadAt<'a> {
+    file: &'a File,
+    offset: u64,
+}
+
+impl Read for ReadAt<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.file.read_at(buf, self.offset)?;
+        self.offset += read as u64;
+        Ok(read)
+    }
+}
+
+fn parse_all(file: impl Read, path: &Path) -> Result<Vec<Mapping>, Error> {
+    BufReader::new(file)
+        .lines()
+        .map(|line| {
+            let line = line.map_err(|e| Error::ProcFsUnavailableError {
+                source: e,
+                path: path.to_owned(),
+            })?;
+            Mapping::parse(&line).ok_or_else(|| Error::ProcFsFormatError {
+                path: path.to_owned(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_all_kinds_of_lines() {
+        let file = Mapping::parse(
+            "7f1c2a000000-7f1c2a1c5000 r-xp 00028000 fd:01 1835042                    /usr/lib/my lib.so (deleted)",
+        )
+        .unwrap();
+        assert_eq!(file.start, 0x7f1c2a000000);
+        assert_eq!(file.end, 0x7f1c2a1c5000);
+        assert_eq!(
+            file.permissions,
+            Permissions {
+                read: true,
+                write: false,
+                execute: true,
+                shared: false
+            }
+        );
+        assert_eq!(file.offset, 0x28000);
+        assert_eq!(file.device, (0xfd, 1));
+        assert_eq!(file.inode, 1835042);
+        assert_eq!(file.pathname, "/usr/lib/my lib.so (deleted)");
+        assert_eq!(
+            file.kind,
+            MappingKind::File {
+                path: "/usr/lib/my lib.so".into(),
+                deleted: true
+            }
+        );
+
+        let anonymous = Mapping::parse("7f1c2a000000-7f1c2a001000 rwxs 00000000 00:00 0 ").unwrap();
+        assert!(anonymous.permissions.shared);
+        assert_eq!(anonymous.kind, MappingKind::Anonymous);
+
+        let vdso =
+            Mapping::parse("7ffd5a7f6000-7ffd5a7f8000 r-xp 00000000 00:00 0          [vdso]")
+                .unwrap();
+        assert_eq!(vdso.kind, MappingKind::Pseudo("vdso".into()));
+        let named =
+            Mapping::parse("7ffd5a7f6000-7ffd5a7f8000 r-xp 00000000 00:00 0  [anon:jit code]")
+                .unwrap();
+        assert_eq!(named.kind, MappingKind::Anonymous);
+        assert_eq!(named.pathname, "[anon:jit code]");
+        let shared =
+            Mapping::parse("7ffd5a7f6000-7ffd5a7f8000 r-xs 00000000 00:01 1234  [anon_shmem:jit]")
+                .unwrap();
+        assert_eq!(shared.kind, MappingKind::Anonymous);
+
+        assert_eq!(
+            Mapping::parse("7ffd5a7f6000-7ffd5a7f8000 r-xp 00000000"),
+            None
+        );
+    }
+
+    #[test]
+    fn reads_own_maps() {
+        let exe = std::env::current_exe().unwrap();
+        let mappings = read_self().unwrap();
+        assert!(mappings
+            .iter()
+            .any(|mapping| mapping.permissions.execute && mapping.path() == Some(exe.as_path())));
+    }
+
+    #[test]
+    fn rereads_kept_open_maps() {
+        keep_self_open().unwrap();
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        let page = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                page_size,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        } as usize;
+        let contains_page = |mappings: Vec<Mapping>| {
+            mappings
+                .iter()
+                .any(|mapping| mapping.start <= page && page < mapping.end)
+        };
+        assert!(contains_page(read_self().unwrap()));
+        unsafe { libc::munmap(page as *mut _, page_size) };
+        assert!(!contains_page(read_self().unwrap()));
+    }
+
+    #[test]
+    fn reopens_maps_kept_open_by_another_process() {
+        // like after a fork: the kept file belongs to another pid, and shows something else than this process
+        let parent = unsafe { libc::getppid() };
+        *SELF_MAPS_FILE.lock().unwrap() =
+            Some((parent, Arc::new(File::open("/dev/null").unwrap())));
+        assert!(!read_self().unwrap().is_empty());
+        let kept = SELF_MAPS_FILE.lock().unwrap();
+        assert_eq!(kept.as_ref().unwrap().0, unsafe { libc::getpid() });
+    }
+}
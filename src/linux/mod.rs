@@ -1,82 +1,452 @@
 use std::{
-    fs::File,
-    io::{BufRead, BufReader},
-    path::Path,
+    ffi::{CStr, OsStr},
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    sync::OnceLock,
 };
 
-use crate::{error::Error, Region};
+use crate::{
+    error::{unix_get_last_error, Error},
+    CheckerConfig, Discovery, MappedFile, Permissions, Region, RepairStrategy, Segment,
+};
 
-pub fn get_executable_regions(
-    skip_libs: bool,
-    include_writable_code: bool,
-) -> Result<Vec<Region>, Error> {
-    let mut regions = vec![];
-    let path = std::path::Path::new("/proc/self/maps");
-    let file = File::open(path).map_err(|e| Error::ProcFsUnavailableError {
-        source: e,
-        path: path.to_owned(),
-    })?;
-
-    let filter = if skip_libs {
-        std::env::current_exe().ok()
-    } else {
-        None
-    };
+use maps::MappingKind;
 
-    for line in BufReader::new(file).lines() {
-        let line = line.map_err(|e| Error::ProcFsUnavailableError {
-            source: e,
-            path: path.to_owned(),
-        })?;
-        let segments: Vec<_> = line.split_whitespace().collect();
-        if !segments[1].starts_with("r-x")
-            && !(include_writable_code && segments[1].starts_with("rwx"))
-        {
+mod backing;
+mod got;
+pub mod maps;
+mod phdr;
+mod symbols;
+
+pub(crate) use backing::{compare_with_file, remap_from_file};
+pub(crate) use got::GotChecker;
+pub(crate) use phdr::{loaded_object_path, loaded_objects_generation};
+pub(crate) use symbols::symbolize;
+
+pub fn get_executable_regions(config: &CheckerConfig) -> Result<Vec<Region>, Error> {
+    match config.discovery {
+        Discovery::ProcMaps => get_mapped_regions(config),
+        Discovery::LoadedObjects => Ok(phdr::get_loaded_regions(config)),
+    }
+}
+
+/// find regions in `/proc/self/maps`
+fn get_mapped_regions(config: &CheckerConfig) -> Result<Vec<Region>, Error> {
+    let filter = config.skip_libs.then(program_path).flatten();
+
+    let mappings = maps::read_self()?;
+    // the dynamic loader knows where it loaded every object. The same file may be mapped more than once, e.g. by a
+    // symbolizer, so the first page of a file in the maps isn't necessarily the loaded one
+    let module_bases = phdr::module_bases();
+
+    let mut regions = vec![];
+    for mapping in mappings {
+        let permissions = mapping.permissions;
+        let file_backed = matches!(mapping.kind, MappingKind::File { .. }) && mapping.inode != 0;
+        let is_code = permissions.read
+            && permissions.execute
+            && (!permissions.write || config.include_writable_code);
+        let is_readonly_data = file_backed
+            && permissions.read
+            && !permissions.write
+            && !permissions.execute
+            && !permissions.shared;
+        let wanted = if is_code {
+            config.checks_segment(Segment::Text)
+        } else {
+            is_readonly_data
+                && (config.checks_segment(Segment::ReadOnlyData)
+                    || config.checks_segment(Segment::Relro))
+        };
+        if !wanted {
             continue;
         }
-        let (start, end) = segments[0]
-            .split_once('-')
-            .ok_or(Error::ProcFsFormatError {
-                path: path.to_owned(),
-            })?;
-        let start = usize::from_str_radix(start, 16).map_err(|_| Error::ProcFsFormatError {
-            path: path.to_owned(),
-        })? as *const u8;
-        let end = usize::from_str_radix(end, 16).map_err(|_| Error::ProcFsFormatError {
-            path: path.to_owned(),
-        })? as *const u8;
-
-        let source = segments
-            .get(5)
-            .copied()
-            .ok_or(Error::ProcFsFormatError {
-                path: path.to_owned(),
-            })?
-            .to_owned();
-
         if let Some(filter_path) = &filter {
-            if Path::new(&source) != filter_path {
+            if mapping.path() != Some(filter_path) {
                 continue;
             }
         }
+        if !config.unbacked_mappings.includes(&mapping.kind) {
+            continue;
+        }
 
-        regions.push(Region { start, end, source })
+        let elf_info = file_backed
+            .then(|| backing::cached_elf_info(&mapping.pathname, mapping.device, mapping.inode));
+        let segment = if is_code {
+            Segment::Text
+        } else if elf_info
+            .as_ref()
+            .and_then(|info| info.relro.as_ref())
+            .is_some_and(|relro| relro_contains(relro, mapping.offset))
+        {
+            Segment::Relro
+        } else {
+            Segment::ReadOnlyData
+        };
+        if !config.checks_segment(segment) {
+            continue;
+        }
+
+        let file = match elf_info {
+            Some(elf_info) => {
+                let build_id = elf_info.build_id;
+                Some(MappedFile {
+                    offset: mapping.offset,
+                    device: mapping.device,
+                    inode: mapping.inode,
+                    module_base: module_bases
+                        .iter()
+                        .find(|(span, _)| span.contains(&mapping.start))
+                        .map_or(mapping.start - mapping.offset as usize, |(_, base)| *base),
+                    build_id,
+                })
+            }
+            None => None,
+        };
+        let source = match mapping.kind {
+            // named anonymous memory keeps its name
+            MappingKind::Anonymous if mapping.pathname.is_empty() => "[anonymous]".to_owned(),
+            _ => mapping.pathname,
+        };
+        let region = Region {
+            start: mapping.start,
+            end: mapping.end,
+            source,
+            permissions,
+            segment,
+            file,
+        };
+        // changing the protection of some pages, as repairs do, makes the kernel split the mapping even after the old
+        // protection is restored. Joining the pieces again keeps the region, and with it its baseline, the same
+        match regions.last_mut() {
+            Some(last) if continues(last, &region) => last.end = region.end,
+            _ => regions.push(region),
+        }
     }
     Ok(regions)
 }
 
+/// whether `next` maps the part of the same file right after `region`, in the same way
+fn continues(region: &Region, next: &Region) -> bool {
+    let (Some(file), Some(next_file)) = (&region.file, &next.file) else {
+        return false;
+    };
+    region.end == next.start
+        && region.source == next.source
+        && region.permissions == next.permissions
+        && region.segment == next.segment
+        && (file.device, file.inode) == (next_file.device, next_file.inode)
+        && file.offset + (region.end - region.start) as u64 == next_file.offset
+}
+
+/// the path of the program, looked up once. `current_exe` needs procfs, so fall back to the path it was executed with
+pub(crate) fn program_path() -> Option<&'static Path> {
+    static PROGRAM: OnceLock<Option<PathBuf>> = OnceLock::new();
+    PROGRAM
+        .get_or_init(|| {
+            std::env::current_exe().ok().or_else(|| {
+                let execfn = unsafe { libc::getauxval(libc::AT_EXECFN) } as *const libc::c_char;
+                if execfn.is_null() {
+                    return None;
+                }
+                let path = PathBuf::from(OsStr::from_bytes(
+                    unsafe { CStr::from_ptr(execfn) }.to_bytes(),
+                ));
+                Some(std::fs::canonicalize(&path).unwrap_or(path))
+            })
+        })
+        .as_deref()
+}
+
+/// open everything discovery needs while the checker is set up, so it keeps working after the process sandboxed
+/// itself with seccomp or Landlock
+pub(crate) fn prepare(config: &CheckerConfig) -> Result<(), Error> {
+    // the GOT check reads it with either discovery, but falls back to the loaded objects without procfs
+    let kept_open = maps::keep_self_open();
+    if config.discovery == Discovery::ProcMaps {
+        kept_open?;
+    }
+    // reads the ELF headers of every file that is mapped right now
+    if !config.skip_system_regions {
+        get_executable_regions(config)?;
+    }
+    Ok(())
+}
+
+/// the system calls a checker with this config makes after [prepare], see
+/// [steady_state_syscalls](crate::ProcessConsistencyChecker::steady_state_syscalls)
+pub(crate) fn steady_state_syscalls(config: &CheckerConfig) -> Vec<&'static str> {
+    // locks, channels and sleeping between rounds, allocations, and reading the time if the vdso can't
+    let mut syscalls = vec![
+        "futex",
+        "mmap",
+        "munmap",
+        "mremap",
+        "madvise",
+        "brk",
+        "clock_gettime",
+    ];
+    if config.discovery == Discovery::ProcMaps || config.check_got {
+        // rereading /proc/self/maps, after checking it was opened by this process
+        syscalls.extend(["getpid", "pread64"]);
+    }
+    if config.discovery == Discovery::LoadedObjects {
+        // checking which files the loaded objects belong to
+        syscalls.push("statx");
+    }
+    if config.safe_reads {
+        syscalls.extend(["getpid", "process_vm_readv"]);
+    }
+    if config.repair != RepairStrategy::Disabled {
+        syscalls.push("mprotect");
+    }
+    // these features read files whenever they find a file they haven't seen yet, as does discovery when libraries are
+    // loaded after setup
+    let reads_files = config.verify_against_file
+        || config.symbolize
+        || config.check_got
+        || config.repair == RepairStrategy::RemapFile
+        || !config.search_once;
+    if reads_files {
+        syscalls.extend([
+            "openat", "statx", "fstat", "read", "pread64", "lseek", "close",
+        ]);
+    }
+    syscalls.sort_unstable();
+    syscalls.dedup();
+    syscalls
+}
+
+/// copy memory of this process with `process_vm_readv`, which fails gracefully instead of crashing if the memory
+/// isn't mapped. Returns None if any part of the range isn't readable
+pub(crate) fn read_memory(start: usize, len: usize) -> Result<Option<Vec<u8>>, Error> {
+    // read in pieces, so a large region doesn't need a huge single copy in the kernel
+    const PIECE: usize = 1 << 20;
+    let mut contents = vec![0u8; len];
+    let pid = unsafe { libc::getpid() };
+    for (i, piece) in contents.chunks_mut(PIECE).enumerate() {
+        let local = libc::iovec {
+            iov_base: piece.as_mut_ptr() as *mut _,
+            iov_len: piece.len(),
+        };
+        let remote = libc::iovec {
+            iov_base: (start + i * PIECE) as *mut _,
+            iov_len: piece.len(),
+        };
+        let read = unsafe { libc::process_vm_readv(pid, &local, 1, &remote, 1, 0) };
+        if read < 0 {
+            if std::io::Error::last_os_error().raw_os_error() == Some(libc::EFAULT) {
+                return Ok(None);
+            }
+            return Err(unix_get_last_error("process_vm_readv"));
+        }
+        // a partial read means the rest of the piece isn't mapped
+        if read as usize != piece.len() {
+            return Ok(None);
+        }
+    }
+    Ok(Some(contents))
+}
+
+/// whether a mapping starting at `offset` belongs to RELRO. The loader protects it starting at the page
+/// containing its first byte
+fn relro_contains(relro: &std::ops::Range<u64>, offset: u64) -> bool {
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as u64;
+    (relro.start & !(page_size - 1)..relro.end).contains(&offset)
+}
+
+/// overwrite memory that is mapped without write permission, e.g. to restore corrupted code
+///
+/// # SAFETY
+/// `start..start + bytes.len()` has to be mapped with the given permissions. Any other thread executing the
+/// overwritten range at the same time might see partially written code
+pub(crate) unsafe fn write_code(
+    start: *const u8,
+    bytes: &[u8],
+    permissions: Permissions,
+) -> Result<(), Error> {
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+    let page_start = (start as usize & !(page_size - 1)) as *mut libc::c_void;
+    let len = start as usize + bytes.len() - page_start as usize;
+    let original = permissions.prot();
+
+    // keep the pages executable if the system allows it, so other threads running this code don't crash.
+    // Many hardened systems forbid writable and executable mappings though
+    if unsafe { libc::mprotect(page_start, len, original | libc::PROT_WRITE) } != 0
+        && unsafe { libc::mprotect(page_start, len, libc::PROT_READ | libc::PROT_WRITE) } != 0
+    {
+        return Err(unix_get_last_error("mprotect"));
+    }
+    unsafe { std::ptr::copy_nonoverlapping(bytes.as_ptr(), start as *mut u8, bytes.len()) };
+    let restored = unsafe { libc::mprotect(page_start, len, original) };
+    flush_instruction_cache(start, bytes.len());
+    if restored != 0 {
+        return Err(unix_get_last_error("mprotect"));
+    }
+    Ok(())
+}
+
+impl Permissions {
+    /// the protection flags for mmap and mprotect
+    pub(crate) fn prot(&self) -> libc::c_int {
+        let mut prot = libc::PROT_NONE;
+        if self.read {
+            prot |= libc::PROT_READ;
+        }
+        if self.write {
+            prot |= libc::PROT_WRITE;
+        }
+        if self.execute {
+            prot |= libc::PROT_EXEC;
+        }
+        prot
+    }
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn flush_instruction_cache(_start: *const u8, _len: usize) {
+    // x86 keeps instruction caches coherent with data writes
+}
+
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+fn flush_instruction_cache(start: *const u8, len: usize) {
+    extern "C" {
+        // provided by libgcc/compiler-rt
+        fn __clear_cache(start: *mut libc::c_char, end: *mut libc::c_char);
+    }
+    unsafe { __clear_cache(start as *mut _, start.add(len) as *mut _) };
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::Segments;
 
     #[test]
     fn test_all_combinations() {
-        println!("{:#?}", get_executable_regions(false, false));
+        let regions = |skip_libs, include_writable_code| {
+            get_executable_regions(&CheckerConfig {
+                skip_libs,
+                include_writable_code,
+                ..Default::default()
+            })
+        };
+        println!("{:#?}", regions(false, false));
         println!("----");
-        println!("{:#?}", get_executable_regions(true, false));
-        assert!(get_executable_regions(false, false).unwrap().len() > 2);
-        assert!(get_executable_regions(false, true).unwrap().len() > 2);
-        assert!(get_executable_regions(true, false).unwrap().len() <= 2);
-        assert!(get_executable_regions(true, true).unwrap().len() <= 2);
+        println!("{:#?}", regions(true, false));
+        assert!(regions(false, false).unwrap().len() > 2);
+        assert!(regions(false, true).unwrap().len() > 2);
+        assert!(regions(true, false).unwrap().len() <= 2);
+        assert!(regions(true, true).unwrap().len() <= 2);
+    }
+
+    #[test]
+    fn regions_describe_their_file() {
+        let address = regions_describe_their_file as fn() as usize;
+        let region = get_executable_regions(&CheckerConfig {
+            skip_libs: true,
+            ..Default::default()
+        })
+        .unwrap()
+        .into_iter()
+        .find(|region| (region.start..region.end).contains(&address))
+        .unwrap();
+        assert!(region.permissions.read && region.permissions.execute);
+        assert!(!region.permissions.write);
+
+        let file = region.file.as_ref().unwrap();
+        assert_ne!(file.inode, 0);
+        assert!(file.module_base <= region.start);
+        assert_eq!(
+            region.module_offset(address),
+            Some(address - file.module_base)
+        );
+        assert_eq!(region.module_offset(region.end), None);
+    }
+
+    #[test]
+    fn module_base_ignores_other_mappings_of_the_file() {
+        let address = libc::getpid as *const () as usize;
+        let libc_region = || {
+            get_executable_regions(&CheckerConfig::default())
+                .unwrap()
+                .into_iter()
+                .find(|region| (region.start..region.end).contains(&address))
+                .unwrap()
+        };
+        let mut info: libc::Dl_info = unsafe { std::mem::zeroed() };
+        assert_ne!(unsafe { libc::dladdr(address as *const _, &mut info) }, 0);
+        let loaded_base = info.dli_fbase as usize;
+        let region = libc_region();
+        assert_eq!(region.file.as_ref().unwrap().module_base, loaded_base);
+
+        // map the file again, like a symbolizer reading its debug info
+        let file = std::fs::File::open(&region.source).unwrap();
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        let mapped = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                page_size,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                std::os::unix::io::AsRawFd::as_raw_fd(&file),
+                0,
+            )
+        };
+        assert_ne!(mapped, libc::MAP_FAILED);
+        let region = libc_region();
+        unsafe { libc::munmap(mapped, page_size) };
+        assert_eq!(region.file.unwrap().module_base, loaded_base);
+    }
+
+    #[test]
+    fn reads_survive_unmapped_memory() {
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        let page = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                2 * page_size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        } as usize;
+        assert_ne!(page as *mut libc::c_void, libc::MAP_FAILED);
+        unsafe { *(page as *mut u8) = 42 };
+        let contents = read_memory(page, 2 * page_size).unwrap().unwrap();
+        assert_eq!(contents[0], 42);
+
+        unsafe { libc::munmap((page + page_size) as *mut _, page_size) };
+        assert_eq!(read_memory(page, 2 * page_size).unwrap(), None);
+        unsafe { libc::munmap(page as *mut _, page_size) };
+        assert_eq!(read_memory(page, page_size).unwrap(), None);
+    }
+
+    #[test]
+    fn finds_readonly_data() {
+        let regions = get_executable_regions(&CheckerConfig {
+            skip_libs: true,
+            segments: Segments {
+                text: false,
+                readonly_data: true,
+                relro: true,
+            },
+            ..Default::default()
+        })
+        .unwrap();
+        let segments: Vec<_> = regions.iter().map(|region| region.segment).collect();
+        assert!(segments.contains(&Segment::ReadOnlyData));
+        assert!(segments.contains(&Segment::Relro));
+        assert!(regions
+            .iter()
+            .all(|region| !region.permissions.write && !region.permissions.execute));
+
+        // string literals live in .rodata
+        let literal = "finds_readonly_data".as_ptr() as usize;
+        assert!(regions
+            .iter()
+            .any(|region| region.segment == Segment::ReadOnlyData
+                && (region.start..region.end).contains(&literal)));
     }
 }
@@ -0,0 +1,338 @@
+use std::{
+    collections::BTreeMap,
+    ffi::{c_void, CStr, OsStr},
+    ops::Range,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use super::backing;
+use crate::{CheckerConfig, MappedFile, Permissions, Region, Segment, UnbackedMappings};
+
+/// A program header of a loaded object
+#[derive(Debug, Clone, Copy)]
+struct ProgramHeader {
+    kind: u32,
+    flags: u32,
+    offset: u64,
+    address: usize,
+    size: usize,
+}
+
+/// An object loaded by the dynamic loader: the program, shared libraries and the vdso
+#[derive(Debug)]
+struct LoadedObject {
+    name: String,
+    /// difference between the addresses in the program headers and in memory
+    bias: usize,
+    headers: Vec<ProgramHeader>,
+}
+
+impl LoadedObject {
+    /// where the first page of the file is loaded
+    fn module_base(&self) -> usize {
+        self.headers
+            .iter()
+            .find(|header| header.kind == libc::PT_LOAD)
+            .map_or(self.bias, |header| {
+                self.bias + header.address - header.offset as usize
+            })
+    }
+
+    /// the addresses the object's loadable segments span, including the partial pages around them
+    fn span(&self, page_size: usize) -> Option<Range<usize>> {
+        let loads = self
+            .headers
+            .iter()
+            .filter(|header| header.kind == libc::PT_LOAD);
+        let start = loads.clone().map(|header| header.address).min()?;
+        let end = loads.map(|header| header.address + header.size).max()?;
+        Some((self.bias + start) & !(page_size - 1)..self.bias + end)
+    }
+}
+
+impl ProgramHeader {
+    fn is_code(&self) -> bool {
+        self.kind == libc::PT_LOAD && self.flags & libc::PF_X != 0
+    }
+
+    fn permissions(&self) -> Permissions {
+        Permissions {
+            read: self.flags & libc::PF_R != 0,
+            write: self.flags & libc::PF_W != 0,
+            execute: self.flags & libc::PF_X != 0,
+            shared: false,
+        }
+    }
+}
+
+/// all objects known to the dynamic loader, the program first
+fn loaded_objects() -> Vec<LoadedObject> {
+    unsafe extern "C" fn collect(
+        info: *mut libc::dl_phdr_info,
+        _size: libc::size_t,
+        objects: *mut c_void,
+    ) -> libc::c_int {
+        let objects = unsafe { &mut *(objects as *mut Vec<LoadedObject>) };
+        let info = unsafe { &*info };
+        let name = if info.dlpi_name.is_null() {
+            String::new()
+        } else {
+            unsafe { CStr::from_ptr(info.dlpi_name) }
+                .to_string_lossy()
+                .into_owned()
+        };
+        // the header fields are 32 bit on 32 bit targets
+        #[allow(clippy::useless_conversion)]
+        let headers = (0..info.dlpi_phnum as usize)
+            .map(|i| {
+                let header = unsafe { &*info.dlpi_phdr.add(i) };
+                ProgramHeader {
+                    kind: header.p_type,
+                    flags: header.p_flags,
+                    offset: header.p_offset.into(),
+                    address: header.p_vaddr as usize,
+                    size: header.p_memsz as usize,
+                }
+            })
+            .collect();
+        objects.push(LoadedObject {
+            name,
+            bias: info.dlpi_addr as usize,
+            headers,
+        });
+        0
+    }
+
+    let mut objects: Vec<LoadedObject> = vec![];
+    unsafe { libc::dl_iterate_phdr(Some(collect), &mut objects as *mut _ as *mut c_void) };
+    objects
+}
+
+/// the number of objects loaded and unloaded since the program started. These only change when the set of loaded
+/// objects changes, which makes them a cheap way to detect when regions have to be rediscovered. None if the dynamic
+/// loader doesn't provide them
+pub(crate) fn loaded_objects_generation() -> Option<(u64, u64)> {
+    unsafe extern "C" fn first(
+        info: *mut libc::dl_phdr_info,
+        size: libc::size_t,
+        generation: *mut c_void,
+    ) -> libc::c_int {
+        let generation = unsafe { &mut *(generation as *mut Option<(u64, u64)>) };
+        // older loaders pass a shorter struct without the counters
+        if size >= std::mem::offset_of!(libc::dl_phdr_info, dlpi_tls_modid) {
+            let info = unsafe { &*info };
+            *generation = Some((info.dlpi_adds, info.dlpi_subs));
+        }
+        // the counters are the same for every object, so stop after the first
+        1
+    }
+
+    let mut generation = None;
+    unsafe { libc::dl_iterate_phdr(Some(first), &mut generation as *mut _ as *mut c_void) };
+    generation
+}
+
+/// the address range and load base of every loaded object. Other mappings of the same files, e.g. by a symbolizer
+/// reading debug info, aren't included
+pub(crate) fn module_bases() -> Vec<(Range<usize>, usize)> {
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+    loaded_objects()
+        .iter()
+        .filter_map(|object| Some((object.span(page_size)?, object.module_base())))
+        .collect()
+}
+
+/// the memory of the object loaded at `module_base` that the loader writes to: writable segments and the ones it
+/// makes read-only after relocation. None if no object is loaded there
+pub(crate) fn relocated_ranges(module_base: usize) -> Option<Vec<Range<usize>>> {
+    let object = loaded_objects()
+        .into_iter()
+        .find(|object| object.module_base() == module_base)?;
+    Some(
+        object
+            .headers
+            .iter()
+            .filter(|header| {
+                header.kind == libc::PT_LOAD && header.flags & libc::PF_W != 0
+                    || header.kind == libc::PT_GNU_RELRO
+            })
+            .map(|header| object.bias + header.address..object.bias + header.address + header.size)
+            .collect(),
+    )
+}
+
+/// the real path of a loaded object, given the name it was loaded with like `libz.so.1`
+pub(crate) fn loaded_object_path(name: &OsStr) -> Option<PathBuf> {
+    loaded_objects()
+        .into_iter()
+        .filter(|object| Path::new(&object.name).file_name() == Some(name))
+        .find_map(|object| std::fs::canonicalize(object.name).ok())
+}
+
+/// the real path of a loaded file, resolved only once per file so discovery doesn't walk symlinks every time
+fn cached_real_path(path: &str, device: (u32, u32), inode: u64) -> String {
+    /// path, device and inode
+    type FileKey = (String, (u32, u32), u64);
+    static REAL_PATHS: Mutex<BTreeMap<FileKey, String>> = Mutex::new(BTreeMap::new());
+    REAL_PATHS
+        .lock()
+        .unwrap()
+        .entry((path.to_owned(), device, inode))
+        .or_insert_with(|| {
+            std::fs::canonicalize(path).map_or_else(
+                |_| path.to_owned(),
+                |path| path.to_string_lossy().into_owned(),
+            )
+        })
+        .clone()
+}
+
+/// find regions in the program headers of all loaded objects, see [Discovery::LoadedObjects](crate::Discovery)
+pub(crate) fn get_loaded_regions(config: &CheckerConfig) -> Vec<Region> {
+    let mut regions = vec![];
+    for (index, object) in loaded_objects().into_iter().enumerate() {
+        // the program is always reported first
+        let is_program = index == 0;
+        if config.skip_libs && !is_program {
+            break;
+        }
+        let module_base = object.module_base();
+        let mut source = match is_program.then(super::program_path).flatten() {
+            Some(path) => path.to_string_lossy().into_owned(),
+            None if object.name.is_empty() => "[program]".to_owned(),
+            None => object.name,
+        };
+        let metadata = source
+            .starts_with('/')
+            .then(|| std::fs::metadata(&source).ok())
+            .flatten();
+        // objects that don't come from a file, like the vdso, are treated like pseudo mappings
+        if metadata.is_none() && config.unbacked_mappings == UnbackedMappings::Skip {
+            continue;
+        }
+        let elf_info = metadata.as_ref().map(|metadata| {
+            let device = (libc::major(metadata.dev()), libc::minor(metadata.dev()));
+            // the loader reports the path a library was found at, which may be a symlink. Report the real path
+            // like procfs does, so sources are the same for both kinds of discovery
+            source = cached_real_path(&source, device, metadata.ino());
+            (
+                device,
+                metadata.ino(),
+                backing::cached_elf_info(&source, device, metadata.ino()),
+            )
+        });
+
+        for header in &object.headers {
+            let permissions = header.permissions();
+            let segment = if header.is_code() {
+                if permissions.write && !config.include_writable_code {
+                    continue;
+                }
+                Segment::Text
+            } else if header.kind == libc::PT_LOAD && permissions.read && !permissions.write {
+                Segment::ReadOnlyData
+            } else if header.kind == libc::PT_GNU_RELRO {
+                Segment::Relro
+            } else {
+                continue;
+            };
+            if !config.checks_segment(segment) || header.size == 0 {
+                continue;
+            }
+            let start = object.bias + header.address;
+            regions.push(Region {
+                start,
+                end: start + header.size,
+                source: source.clone(),
+                permissions: Permissions {
+                    read: true,
+                    // the loader makes RELRO read-only after relocating it
+                    write: permissions.write && segment != Segment::Relro,
+                    ..permissions
+                },
+                segment,
+                file: elf_info
+                    .as_ref()
+                    .map(|(device, inode, elf_info)| MappedFile {
+                        offset: header.offset,
+                        device: *device,
+                        inode: *inode,
+                        module_base,
+                        build_id: elf_info.build_id.clone(),
+                    }),
+            });
+        }
+    }
+    regions
+}
+
+/// code of all loaded objects, used when procfs is unavailable
+pub(crate) fn executable_ranges() -> Vec<Range<usize>> {
+    loaded_objects()
+        .iter()
+        .flat_map(|object| {
+            object
+                .headers
+                .iter()
+                .filter(|header| header.is_code())
+                .map(|header| {
+                    object.bias + header.address..object.bias + header.address + header.size
+                })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generation_changes_with_loaded_objects() {
+        let is_libm = |object: &LoadedObject| object.name.contains("libm.so");
+        let was_loaded = loaded_objects().iter().any(is_libm);
+        let before = loaded_objects_generation().unwrap();
+        assert_eq!(loaded_objects_generation(), Some(before));
+
+        let library = std::ffi::CString::new("libm.so.6").unwrap();
+        let handle = unsafe { libc::dlopen(library.as_ptr(), libc::RTLD_NOW) };
+        assert!(!handle.is_null());
+        let after = loaded_objects_generation().unwrap();
+        unsafe { libc::dlclose(handle) };
+        if !was_loaded {
+            assert!(after.0 > before.0);
+        }
+    }
+
+    #[test]
+    fn finds_exact_segments() {
+        let _lock = crate::tests::CODE_LOCK.lock().unwrap();
+        let address = finds_exact_segments as fn() as usize;
+        let regions = get_loaded_regions(&CheckerConfig::default());
+        let own = regions
+            .iter()
+            .find(|region| (region.start..region.end).contains(&address))
+            .unwrap();
+        assert_eq!(
+            std::path::Path::new(&own.source),
+            std::env::current_exe().unwrap()
+        );
+        assert_eq!(own.segment, Segment::Text);
+        assert!(regions.len() > 2);
+
+        // every region lies within a mapping procfs knows about with the same path, and matches the file
+        let mappings = super::super::maps::read_self().unwrap();
+        for region in regions.iter().filter(|region| region.file.is_some()) {
+            assert!(
+                mappings.iter().any(|mapping| mapping.start <= region.start
+                    && region.end <= mapping.end
+                    && mapping.pathname == region.source),
+                "{:#x?}",
+                region
+            );
+            let current = unsafe { region.as_slice() };
+            assert_eq!(backing::compare_with_file(region, current), Ok(vec![]));
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use object::{read::ReadCache, Object, ObjectSegment, ObjectSymbol, SymbolKind};
+
+use super::backing::open_mapped_file;
+use crate::{Location, Region};
+
+/// resolve offsets in a file backed region to the nearest symbol in the file's `.symtab` or `.dynsym`, and to
+/// source lines if the `dwarf` feature is enabled. Offsets are returned unresolved if the file can't be read
+pub(crate) fn symbolize(region: &Region, offsets: &[usize]) -> Vec<Location> {
+    let unresolved = |offset| Location {
+        offset,
+        function: None,
+        function_offset: 0,
+        file: None,
+        line: None,
+    };
+    let Some(file) = region.file.as_ref() else {
+        return offsets.iter().copied().map(unresolved).collect();
+    };
+    let Ok(opened) = open_mapped_file(&region.source, file.device, file.inode) else {
+        return offsets.iter().copied().map(unresolved).collect();
+    };
+    let cache = ReadCache::new(&opened);
+    let Ok(elf) = object::File::parse(&cache) else {
+        return offsets.iter().copied().map(unresolved).collect();
+    };
+
+    let mut symbols: Vec<_> = elf
+        .symbols()
+        .chain(elf.dynamic_symbols())
+        .filter(|symbol| symbol.kind() == SymbolKind::Text && symbol.address() != 0)
+        .filter_map(|symbol| Some((symbol.address(), symbol.size(), symbol.name().ok()?)))
+        .collect();
+    symbols.sort_unstable_by_key(|(address, _, _)| *address);
+    #[cfg(feature = "dwarf")]
+    let sections = dwarf::sections(&elf);
+    #[cfg(feature = "dwarf")]
+    let lines = sections
+        .as_ref()
+        .and_then(|sections| dwarf::lines(sections, elf.is_little_endian()));
+
+    offsets
+        .iter()
+        .map(|&offset| {
+            let mut location = unresolved(offset);
+            let Some(address) = file_address(&elf, file.offset + offset as u64) else {
+                return location;
+            };
+            // last symbol starting at or before the address
+            let nearest = symbols.partition_point(|(start, _, _)| *start <= address);
+            if let Some((start, size, name)) = nearest.checked_sub(1).map(|i| symbols[i]) {
+                if size == 0 || address < start + size {
+                    location.function = Some(rustc_demangle::demangle(name).to_string());
+                    location.function_offset = (address - start) as usize;
+                }
+            }
+            #[cfg(feature = "dwarf")]
+            if let Some((file, line)) = lines.as_ref().and_then(|lines| dwarf::find(lines, address))
+            {
+                location.file = Some(file);
+                location.line = line;
+            }
+            location
+        })
+        .collect()
+}
+
+/// translate a file offset to the virtual address it is loaded at according to the program headers
+fn file_address<'data>(elf: &impl Object<'data>, offset: u64) -> Option<u64> {
+    elf.segments().find_map(|segment| {
+        let (start, size) = segment.file_range();
+        (start..start + size)
+            .contains(&offset)
+            .then(|| segment.address() + offset - start)
+    })
+}
+
+#[cfg(feature = "dwarf")]
+mod dwarf {
+    use std::borrow::Cow;
+
+    use addr2line::gimli;
+    use object::{Object, ObjectSection};
+
+    pub(super) type Sections<'data> = gimli::DwarfSections<Cow<'data, [u8]>>;
+    pub(super) type Lines<'a> = addr2line::Context<gimli::EndianSlice<'a, gimli::RunTimeEndian>>;
+
+    /// the DWARF debug sections of a file
+    pub(super) fn sections<'data>(elf: &impl Object<'data>) -> Option<Sections<'data>> {
+        gimli::DwarfSections::load(|id| {
+            let data = elf
+                .section_by_name(id.name())
+                .and_then(|section| section.uncompressed_data().ok())
+                .unwrap_or_default();
+            Ok::<_, gimli::Error>(data)
+        })
+        .ok()
+    }
+
+    pub(super) fn lines<'a>(sections: &'a Sections<'_>, little_endian: bool) -> Option<Lines<'a>> {
+        let endian = if little_endian {
+            gimli::RunTimeEndian::Little
+        } else {
+            gimli::RunTimeEndian::Big
+        };
+        addr2line::Context::from_dwarf(
+            sections.borrow(|section| gimli::EndianSlice::new(section, endian)),
+        )
+        .ok()
+    }
+
+    /// source file and line of an address
+    pub(super) fn find(lines: &Lines<'_>, address: u64) -> Option<(String, Option<u32>)> {
+        let location = lines.find_location(address).ok()??;
+        Some((location.file?.to_owned(), location.line))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[inline(never)]
+    fn symbolize_target() -> u32 {
+        std::hint::black_box(7)
+    }
+
+    #[test]
+    fn resolves_own_function() {
+        let address = symbolize_target as fn() -> u32 as usize;
+        let region = crate::get_all_regions(&crate::CheckerConfig {
+            skip_libs: true,
+            ..Default::default()
+        })
+        .unwrap()
+        .into_iter()
+        .find(|region| (region.start..region.end).contains(&address))
+        .unwrap();
+
+        let locations = symbolize(&region, &[address - region.start + 1]);
+        assert_eq!(locations.len(), 1);
+        let function = locations[0].function.as_ref().unwrap();
+        assert!(function.contains("symbolize_target"), "{}", function);
+        assert_eq!(locations[0].function_offset, 1);
+        #[cfg(feature = "dwarf")]
+        assert!(locations[0].file.as_ref().unwrap().ends_with("symbols.rs"));
+    }
+}
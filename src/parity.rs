@@ -0,0 +1,89 @@
+/// XOR parity over groups of chunks of a region. Together with the per chunk hashes this allows rebuilding
+/// a single corrupted chunk per group
+#[derive(Debug)]
+pub(crate) struct Parity {
+    chunk_size: usize,
+    group_size: usize,
+    /// one parity chunk per group of group_size chunks
+    groups: Vec<Box<[u8]>>,
+}
+
+impl Parity {
+    pub(crate) fn new(bytes: &[u8], chunk_size: usize, group_size: usize) -> Self {
+        let groups = bytes
+            .chunks(chunk_size * group_size)
+            .map(|group| {
+                let mut parity = vec![0; chunk_size].into_boxed_slice();
+                for chunk in group.chunks(chunk_size) {
+                    xor_into(&mut parity, chunk);
+                }
+                parity
+            })
+            .collect();
+        Self {
+            chunk_size,
+            group_size,
+            groups,
+        }
+    }
+
+    /// true if every group contains at most one of the changed chunks
+    pub(crate) fn can_correct(&self, changed_chunks: &[usize]) -> bool {
+        let mut groups: Vec<_> = changed_chunks
+            .iter()
+            .map(|chunk| chunk / self.group_size)
+            .collect();
+        let changed = groups.len();
+        groups.sort_unstable();
+        groups.dedup();
+        groups.len() == changed
+    }
+
+    /// reconstruct the original contents of a chunk from the parity and the other chunks of its group,
+    /// which have to be intact
+    pub(crate) fn rebuild(&self, bytes: &[u8], chunk: usize) -> Vec<u8> {
+        let group = chunk / self.group_size;
+        let mut rebuilt = self.groups[group].to_vec();
+        let group_start = group * self.group_size * self.chunk_size;
+        let group_end = (group_start + self.group_size * self.chunk_size).min(bytes.len());
+        for (i, other) in bytes[group_start..group_end]
+            .chunks(self.chunk_size)
+            .enumerate()
+        {
+            if group * self.group_size + i != chunk {
+                xor_into(&mut rebuilt, other);
+            }
+        }
+        let chunk_len = self.chunk_size.min(bytes.len() - chunk * self.chunk_size);
+        rebuilt.truncate(chunk_len);
+        rebuilt
+    }
+}
+
+fn xor_into(target: &mut [u8], source: &[u8]) {
+    for (t, s) in target.iter_mut().zip(source) {
+        *t ^= s;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rebuilds_one_chunk_per_group() {
+        let original: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let parity = Parity::new(&original, 64, 4);
+
+        let mut corrupted = original.clone();
+        corrupted[70] ^= 0x01;
+        corrupted[300] = 0;
+        corrupted[999] ^= 0xff;
+        assert!(parity.can_correct(&[1, 4, 15]));
+        assert_eq!(parity.rebuild(&corrupted, 1), &original[64..128]);
+        assert_eq!(parity.rebuild(&corrupted, 4), &original[256..320]);
+        assert_eq!(parity.rebuild(&corrupted, 15), &original[960..1000]);
+
+        assert!(!parity.can_correct(&[0, 3]));
+    }
+}
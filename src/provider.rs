@@ -0,0 +1,222 @@
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+    sync::{Arc, Mutex},
+};
+
+use crate::{Permissions, Region, Segment};
+
+/// Error returned by a [RegionProvider]
+pub type ProviderError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Supplies regions to check in addition to, or instead of, the ones the checker finds itself, see
+/// [region_provider](crate::ProcessConsistencyChecker::region_provider)
+///
+/// # SAFETY
+/// Every returned region has to stay readable until the provider is dropped, even after later calls of
+/// [regions](Self::regions) stopped returning it. Besides the checker thread,
+/// [verify_now](crate::CheckerHandle::verify_now), [capture_snapshot](crate::ProcessConsistencyChecker::capture_snapshot)
+/// and [benchmark](crate::ProcessConsistencyChecker::benchmark) call it from other threads, and may still be reading
+/// a region while the checker already got the next set
+pub unsafe trait RegionProvider: Send + Sync {
+    /// all regions to check right now. Called once per round by the checker, and possibly at the same time from
+    /// other threads
+    fn regions(&self) -> Result<Vec<Region>, ProviderError>;
+}
+
+unsafe impl<P: RegionProvider + ?Sized> RegionProvider for Arc<P> {
+    fn regions(&self) -> Result<Vec<Region>, ProviderError> {
+        (**self).regions()
+    }
+}
+
+/// The providers of a config. Compared and hashed by identity, so the config stays comparable
+#[derive(Clone, Default)]
+pub(crate) struct Providers(pub(crate) Vec<Arc<dyn RegionProvider>>);
+
+impl fmt::Debug for Providers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Providers({})", self.0.len())
+    }
+}
+
+impl PartialEq for Providers {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.len() == other.0.len() && self.0.iter().zip(&other.0).all(|(a, b)| Arc::ptr_eq(a, b))
+    }
+}
+
+impl Eq for Providers {}
+
+impl Hash for Providers {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for provider in &self.0 {
+            (Arc::as_ptr(provider) as *const () as usize).hash(state);
+        }
+    }
+}
+
+/// A [RegionProvider] serving synthetic regions from memory it owns, to test the handling of added, removed and
+/// changed regions without depending on the memory layout of the process
+///
+/// ```rust
+///   use std::sync::Arc;
+///   use process_consistency::{CheckerAction, CheckerEvent, MockRegionProvider, ProcessConsistencyChecker};
+///   let mock = Arc::new(MockRegionProvider::new());
+///   let region = mock.add("table", vec![0u8; 64]);
+///   let mut rounds = 0;
+///   ProcessConsistencyChecker::new()
+///     .system_regions(false)
+///     .check_period(std::time::Duration::ZERO)
+///     .region_provider(mock.clone())
+///     .run(|event| match event {
+///       CheckerEvent::ScanCompleted { .. } => {
+///         rounds += 1;
+///         mock.write(&region, 3, &[0xff]);
+///         CheckerAction::Continue
+///       }
+///       CheckerEvent::HashMismatch(error) => {
+///         assert_eq!(error.region, region);
+///         CheckerAction::Stop
+///       }
+///       _ => CheckerAction::Continue,
+///     })
+///     .unwrap();
+///   assert_eq!(rounds, 1);
+/// ```
+#[derive(Debug, Default)]
+pub struct MockRegionProvider {
+    state: Mutex<MockState>,
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    regions: Vec<(Region, Vec<u8>)>,
+    /// memory of removed regions, which the checker might still be reading
+    retired: Vec<Vec<u8>>,
+}
+
+impl MockRegionProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// serve a new region with the given contents. Returns the region as the checker reports it, with
+    /// [Segment::Custom] and read-only permissions
+    pub fn add(&self, source: impl Into<String>, contents: impl Into<Vec<u8>>) -> Region {
+        let contents = contents.into();
+        let start = contents.as_ptr() as usize;
+        let region = Region {
+            start,
+            end: start + contents.len(),
+            source: source.into(),
+            permissions: Permissions {
+                read: true,
+                ..Default::default()
+            },
+            segment: Segment::Custom,
+            file: None,
+        };
+        let mut state = self.state.lock().unwrap();
+        state.regions.push((region.clone(), contents));
+        region
+    }
+
+    /// stop serving a region, so the checker reports it as removed. Returns false if it isn't served
+    pub fn remove(&self, region: &Region) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let Some(index) = state
+            .regions
+            .iter()
+            .position(|(served, _)| served == region)
+        else {
+            return false;
+        };
+        let (_, contents) = state.regions.remove(index);
+        state.retired.push(contents);
+        true
+    }
+
+    /// overwrite part of a region, to simulate memory corruption. Like real corruption this can happen while the
+    /// checker is hashing the region, write from the event callback for deterministic results.
+    ///
+    /// Panics if the region isn't served or the bytes don't fit
+    pub fn write(&self, region: &Region, offset: usize, bytes: &[u8]) {
+        let mut state = self.state.lock().unwrap();
+        let (_, contents) = state
+            .regions
+            .iter_mut()
+            .find(|(served, _)| served == region)
+            .expect("region is not served by this provider");
+        assert!(
+            offset + bytes.len() <= contents.len(),
+            "write out of bounds"
+        );
+        let target = contents.as_mut_ptr();
+        for (i, byte) in bytes.iter().enumerate() {
+            // the checker reads this memory through raw pointers
+            unsafe { std::ptr::write_volatile(target.add(offset + i), *byte) };
+        }
+    }
+}
+
+// SAFETY: regions are backed by memory that is kept until the provider is dropped, even after they are removed, so
+// readers on any thread can finish
+unsafe impl RegionProvider for MockRegionProvider {
+    fn regions(&self) -> Result<Vec<Region>, ProviderError> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .regions
+            .iter()
+            .map(|(region, _)| region.clone())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CheckerEvent, ProcessConsistencyChecker};
+
+    #[test]
+    fn mock_regions_are_added_changed_and_removed() {
+        let mock = Arc::new(MockRegionProvider::new());
+        let first = mock.add("first", vec![1u8; 32]);
+        let handle = ProcessConsistencyChecker::new()
+            .system_regions(false)
+            .check_period(std::time::Duration::from_millis(10))
+            .region_provider(mock.clone())
+            .spawn(|_| {})
+            .unwrap();
+        let events = handle.subscribe();
+        let second = mock.add("second", vec![2u8; 32]);
+        events
+            .iter()
+            .find(|event| matches!(event, CheckerEvent::RegionAdded(region) if *region == second))
+            .unwrap();
+
+        handle.pause();
+        mock.write(&first, 31, &[0]);
+        assert!(mock.remove(&second));
+        assert!(!mock.remove(&second));
+        handle.resume();
+        let mut changed = false;
+        let mut removed = false;
+        for event in events.iter() {
+            match event {
+                CheckerEvent::HashMismatch(error) if error.region == first => changed = true,
+                CheckerEvent::RegionRemoved(region) => {
+                    assert_eq!(region, second);
+                    removed = true;
+                }
+                CheckerEvent::ScanCompleted { regions, .. } if changed && removed => {
+                    assert_eq!(regions, 1);
+                    break;
+                }
+                _ => {}
+            }
+        }
+        assert!(changed && removed);
+        handle.stop_and_join().unwrap();
+    }
+}
@@ -0,0 +1,337 @@
+use std::{borrow::Cow, time::Instant};
+
+use crate::{
+    error::Error, golden::byte_diffs, CheckerConfig, Hash, Permissions, Region, RegionHash, Segment,
+};
+
+/// How regions with a [HashMismatch](crate::CheckerEvent::HashMismatch) are repaired, see
+/// [repair()](crate::ProcessConsistencyChecker::repair)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum RepairStrategy {
+    /// don't touch corrupted memory, only report it
+    #[default]
+    Disabled,
+    /// restore the original bytes from a private copy of every region, see
+    /// [golden_copy](crate::ProcessConsistencyChecker::golden_copy)
+    GoldenCopy,
+    /// map the changed pages again from the file the region was loaded from. Doesn't need a copy in memory,
+    /// but only works for file backed regions on Linux, and only if the file on disk still matches the baseline
+    RemapFile,
+    /// rebuild changed chunks from XOR parity, kept for every group of `group_size` chunks. Costs one chunk of memory
+    /// per group instead of a full copy, but can only correct one changed chunk per group. Whether a mismatch is
+    /// correctable is reported in [MemoryEvent::correctable](crate::MemoryEvent::correctable).
+    ///
+    /// The chunks are the same as for hashing, see [chunk_size](crate::ProcessConsistencyChecker::chunk_size)
+    Parity {
+        /// how many chunks share one parity chunk
+        group_size: usize,
+    },
+}
+
+/// restore a corrupted region to the state recorded in `baseline`, then check that it matches the baseline again.
+///
+/// Returns the number of bytes that were rewritten
+///
+/// # SAFETY
+/// same as [Region::read]. Threads executing the corrupted code while it is being repaired might misbehave
+pub(crate) unsafe fn repair(
+    region: &Region,
+    baseline: &RegionHash,
+    config: &CheckerConfig,
+) -> Result<usize, String> {
+    if region.segment == Segment::Custom {
+        return Err("registered regions are not repaired".into());
+    }
+    let written = match config.repair {
+        RepairStrategy::Disabled => return Err("repair is disabled".into()),
+        RepairStrategy::GoldenCopy => unsafe { restore_golden_copy(region, baseline, config) }?,
+        RepairStrategy::RemapFile => unsafe { remap_from_file(region, baseline, config) }?,
+        RepairStrategy::Parity { .. } => unsafe { rebuild_from_parity(region, baseline, config) }?,
+    };
+
+    let repaired = unsafe { RegionHash::compute(region, config, Instant::now()) }
+        .map_err(|e| e.to_string())?
+        .ok_or(UNMAPPED)?;
+    if repaired.hash != baseline.hash {
+        return Err("region still differs from the baseline after repair".into());
+    }
+    Ok(written)
+}
+
+const UNMAPPED: &str = "region was unmapped";
+
+/// the current contents of a region, read like the checker reads them
+///
+/// # SAFETY
+/// same as [repair]
+unsafe fn read_current<'a>(
+    region: &'a Region,
+    config: &CheckerConfig,
+) -> Result<Cow<'a, [u8]>, String> {
+    unsafe { region.read(config) }
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| UNMAPPED.to_owned())
+}
+
+/// # SAFETY
+/// same as [repair]
+unsafe fn restore_golden_copy(
+    region: &Region,
+    baseline: &RegionHash,
+    config: &CheckerConfig,
+) -> Result<usize, String> {
+    let golden = baseline
+        .golden
+        .as_ref()
+        .ok_or("no golden copy of the region available")?
+        .bytes();
+    let diffs = byte_diffs(&golden, &unsafe { read_current(region, config) }?);
+    let (first, last) = match (diffs.first(), diffs.last()) {
+        (Some(first), Some(last)) => (first.offset, last.offset),
+        // memory already changed back
+        _ => return Ok(0),
+    };
+    unsafe {
+        write_code(
+            (region.start + first) as *const u8,
+            &golden[first..=last],
+            region.permissions,
+        )
+    }
+    .map_err(|e| e.to_string())?;
+    Ok(last + 1 - first)
+}
+
+/// # SAFETY
+/// same as [repair]
+unsafe fn rebuild_from_parity(
+    region: &Region,
+    baseline: &RegionHash,
+    config: &CheckerConfig,
+) -> Result<usize, String> {
+    let parity = baseline
+        .parity
+        .as_ref()
+        .ok_or("no parity of the region available")?;
+    let chunk_size = config
+        .effective_chunk_size()
+        .ok_or("parity needs a chunk size")?;
+    let current_bytes = unsafe { read_current(region, config) }?;
+    let current = RegionHash::of_bytes(&current_bytes, config, Instant::now());
+    let changed_chunks = baseline.changed_chunks(&current);
+    if !parity.can_correct(&changed_chunks) {
+        return Err("more than one chunk per parity group changed".into());
+    }
+
+    let rebuilt: Vec<_> = changed_chunks
+        .iter()
+        .map(|&chunk| (chunk, parity.rebuild(&current_bytes, chunk)))
+        .collect();
+    drop(current_bytes);
+    let mut written = 0;
+    for (chunk, bytes) in rebuilt {
+        if Hash::of(&bytes) != baseline.chunks[chunk] {
+            return Err(format!("rebuilt chunk {} doesn't match its hash", chunk));
+        }
+        unsafe {
+            write_code(
+                (region.start + chunk * chunk_size) as *const u8,
+                &bytes,
+                region.permissions,
+            )
+        }
+        .map_err(|e| e.to_string())?;
+        written += bytes.len();
+    }
+    Ok(written)
+}
+
+/// # SAFETY
+/// same as [repair]
+unsafe fn remap_from_file(
+    region: &Region,
+    baseline: &RegionHash,
+    config: &CheckerConfig,
+) -> Result<usize, String> {
+    #[cfg(unix)]
+    return unsafe {
+        crate::linux::remap_from_file(region, config, |contents| {
+            RegionHash::of_bytes(contents, config, Instant::now()).hash == baseline.hash
+        })
+    };
+    #[cfg(windows)]
+    {
+        let _ = (region, baseline, config);
+        Err("remapping from file is only supported on Linux".into())
+    }
+}
+
+/// # SAFETY
+/// see the platform specific implementations
+unsafe fn write_code(
+    start: *const u8,
+    bytes: &[u8],
+    permissions: Permissions,
+) -> Result<(), Error> {
+    #[cfg(unix)]
+    return unsafe { crate::linux::write_code(start, bytes, permissions) };
+    #[cfg(windows)]
+    unsafe {
+        crate::windows::write_code(start, bytes, permissions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{get_all_regions, CheckerAction, CheckerEvent};
+
+    #[inline(never)]
+    fn repair_target() -> u32 {
+        std::hint::black_box(42)
+    }
+
+    fn corrupt_and_repair(
+        repair_strategy: RepairStrategy,
+        safe_reads: bool,
+    ) -> Result<usize, String> {
+        let _lock = crate::tests::CODE_LOCK.lock().unwrap();
+        let config = CheckerConfig {
+            repair: repair_strategy,
+            safe_reads,
+            ..Default::default()
+        };
+        let target = repair_target as fn() -> u32 as *const u8;
+        let region = get_all_regions(&CheckerConfig {
+            skip_libs: true,
+            ..Default::default()
+        })
+        .unwrap()
+        .into_iter()
+        .find(|region| (region.start..region.end).contains(&(target as usize)))
+        .unwrap();
+        let bytes = unsafe { read_current(&region, &config) }
+            .unwrap()
+            .into_owned();
+        let mut baseline = RegionHash::of_bytes(&bytes, &config, Instant::now());
+        baseline.keep_repair_data(&bytes, &config);
+
+        unsafe { write_code(target, &[*target ^ 0x10], region.permissions) }.unwrap();
+        let corrupted = unsafe { RegionHash::compute(&region, &config, Instant::now()) }
+            .unwrap()
+            .unwrap();
+        assert_ne!(corrupted.hash, baseline.hash);
+
+        let result = unsafe { repair(&region, &baseline, &config) };
+        assert_eq!(repair_target(), 42);
+        result
+    }
+
+    /// corrupt code between two rounds of a running checker, and collect the events of the following two rounds
+    fn corrupt_while_checking(repair_strategy: RepairStrategy) -> Vec<CheckerEvent> {
+        let _lock = crate::tests::CODE_LOCK.lock().unwrap();
+        let target = repair_target as fn() -> u32 as *const u8;
+        let original = unsafe { *target };
+        let mut rounds = 0;
+        let mut events = vec![];
+        crate::ProcessConsistencyChecker::new()
+            .skip_libs(true)
+            .check_period(std::time::Duration::ZERO)
+            .repair(repair_strategy)
+            .run(|event| {
+                if rounds > 0 {
+                    events.push(event.clone());
+                }
+                match event {
+                    CheckerEvent::ScanCompleted { .. } => {
+                        rounds += 1;
+                        if rounds == 1 {
+                            let permissions = Permissions {
+                                read: true,
+                                execute: true,
+                                ..Default::default()
+                            };
+                            unsafe { write_code(target, &[original ^ 0x10], permissions) }.unwrap();
+                        }
+                        match rounds {
+                            3 => CheckerAction::Stop,
+                            _ => CheckerAction::Continue,
+                        }
+                    }
+                    _ => CheckerAction::Continue,
+                }
+            })
+            .unwrap();
+        // don't run the code before knowing it is repaired
+        assert_eq!(unsafe { *target }, original, "{:#?}", events);
+        assert_eq!(repair_target(), 42);
+        events
+    }
+
+    fn assert_repaired_in_place(events: Vec<CheckerEvent>) {
+        assert!(
+            events
+                .iter()
+                .any(|event| matches!(event, CheckerEvent::Repaired { .. })),
+            "{:#?}",
+            events
+        );
+        // the repaired region is still the same region afterwards
+        assert!(
+            !events.iter().any(|event| matches!(
+                event,
+                CheckerEvent::RegionAdded(_) | CheckerEvent::RegionRemoved(_)
+            )),
+            "{:#?}",
+            events
+        );
+    }
+
+    #[test]
+    fn checker_repairs_in_place() {
+        assert_repaired_in_place(corrupt_while_checking(RepairStrategy::GoldenCopy));
+        assert_repaired_in_place(corrupt_while_checking(RepairStrategy::Parity {
+            group_size: 16,
+        }));
+        #[cfg(unix)]
+        assert_repaired_in_place(corrupt_while_checking(RepairStrategy::RemapFile));
+    }
+
+    #[test]
+    fn repairs_from_golden_copy() {
+        assert_eq!(corrupt_and_repair(RepairStrategy::GoldenCopy, false), Ok(1));
+    }
+
+    #[test]
+    fn repairs_from_parity() {
+        assert_eq!(
+            corrupt_and_repair(RepairStrategy::Parity { group_size: 16 }, false),
+            Ok(4096)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn repairs_by_remapping_file() {
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        assert_eq!(
+            corrupt_and_repair(RepairStrategy::RemapFile, false),
+            Ok(page_size)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn repairs_with_safe_reads() {
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        assert_eq!(corrupt_and_repair(RepairStrategy::GoldenCopy, true), Ok(1));
+        assert_eq!(
+            corrupt_and_repair(RepairStrategy::Parity { group_size: 16 }, true),
+            Ok(4096)
+        );
+        assert_eq!(
+            corrupt_and_repair(RepairStrategy::RemapFile, true),
+            Ok(page_size)
+        );
+    }
+}
@@ -0,0 +1,330 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    ops::Deref,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use crate::{Hash, MemoryEvent, Permissions, Region, Segment};
+
+/// An immutable value that is checked for unexpected changes, e.g. configuration, keys or lookup tables
+///
+/// The value is hashed when it is sealed, either through its [std::hash::Hash] implementation with [new](Self::new),
+/// so data behind pointers like the contents of a `Vec` is covered too, or byte for byte with
+/// [new_plain](Self::new_plain), for [PlainData] like arrays of floats. Every running checker verifies all sealed
+/// values each round and reports changes as [HashMismatch](crate::CheckerEvent::HashMismatch), with the label as
+/// [source](Region::source) and [Segment::Sealed]. Values with interior mutability can't be sealed meaningfully.
+///
+/// ```rust
+///   use process_consistency::Sealed;
+///   let primes = Sealed::new("primes", vec![2u64, 3, 5, 7, 11]).verify_on_deref(true);
+///   assert_eq!(primes[3], 7);
+///   assert!(primes.verify().is_none());
+///   let weights = Sealed::new_plain("weights", [0.5f32, 0.25, 0.25]);
+///   assert!(weights.verify().is_none());
+/// ```
+pub struct Sealed<T: Send + Sync + 'static> {
+    value: Arc<T>,
+    current_hash: fn(&T) -> Hash,
+    id: u64,
+    region: Region,
+    hash: Hash,
+    sealed_at: Instant,
+    verify_on_deref: bool,
+}
+
+/// Types that consist of nothing but their bytes, so they can be sealed with [Sealed::new_plain]
+///
+/// # Safety
+/// Every byte of the type must be initialized, so it must not contain padding, and all of its contents must be
+/// inline, without pointers to data that should be covered too. `#[repr(C)]` structs of plain data without padding
+/// qualify.
+pub unsafe trait PlainData: Copy + Send + Sync + 'static {}
+
+macro_rules! plain_data {
+    ($($ty:ty),*) => {
+        $(unsafe impl PlainData for $ty {})*
+    };
+}
+
+plain_data!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64, bool, char);
+
+unsafe impl<T: PlainData, const N: usize> PlainData for [T; N] {}
+
+/// hash a value through its [std::hash::Hash] implementation
+fn hash_of_impl<T: std::hash::Hash>(value: &T) -> Hash {
+    let mut hasher = HashWriter::default();
+    value.hash(&mut hasher);
+    hasher.finalize()
+}
+
+/// hash the bytes of a value
+fn hash_of_bytes<T: PlainData>(value: &T) -> Hash {
+    // SAFETY: PlainData guarantees all bytes are initialized
+    let bytes = unsafe {
+        std::slice::from_raw_parts(value as *const T as *const u8, std::mem::size_of::<T>())
+    };
+    Hash::of(bytes)
+}
+
+struct Entry {
+    region: Region,
+    hash: Hash,
+    sealed_at: Instant,
+    /// hashes the value as it is now, type erased so the checker can hash values of any type
+    current_hash: Arc<dyn Fn() -> Hash + Send + Sync>,
+}
+
+struct Registry {
+    next_id: u64,
+    values: BTreeMap<u64, Entry>,
+}
+
+/// all sealed values that are alive, checked by every running checker
+static SEALED: Mutex<Registry> = Mutex::new(Registry {
+    next_id: 0,
+    values: BTreeMap::new(),
+});
+
+impl<T: std::hash::Hash + Send + Sync + 'static> Sealed<T> {
+    /// hash the value through its [std::hash::Hash] implementation and register it with all running checkers.
+    ///
+    /// Only what the implementation feeds into the hasher is covered, which may be more than the reported
+    /// [Region] (e.g. the contents of a `Vec`) or less (e.g. fields it ignores)
+    pub fn new(label: impl Into<String>, value: T) -> Self {
+        Self::seal(label.into(), value, hash_of_impl)
+    }
+}
+
+impl<T: PlainData> Sealed<T> {
+    /// hash the bytes of the value and register it with all running checkers. The reported [Region] covers exactly
+    /// the hashed bytes
+    pub fn new_plain(label: impl Into<String>, value: T) -> Self {
+        Self::seal(label.into(), value, hash_of_bytes)
+    }
+}
+
+impl<T: Send + Sync + 'static> Sealed<T> {
+    fn seal(label: String, value: T, current_hash: fn(&T) -> Hash) -> Self {
+        let value = Arc::new(value);
+        let start = Arc::as_ptr(&value) as usize;
+        let region = Region {
+            start,
+            end: start + std::mem::size_of::<T>(),
+            source: label,
+            permissions: Permissions {
+                read: true,
+                ..Default::default()
+            },
+            segment: Segment::Sealed,
+            file: None,
+        };
+        let hash = current_hash(&value);
+        let sealed_at = Instant::now();
+
+        let mut registry = SEALED.lock().unwrap();
+        let id = registry.next_id;
+        registry.next_id += 1;
+        registry.values.insert(
+            id,
+            Entry {
+                region: region.clone(),
+                hash,
+                sealed_at,
+                current_hash: {
+                    let value = value.clone();
+                    Arc::new(move || current_hash(&value))
+                },
+            },
+        );
+        Self {
+            value,
+            current_hash,
+            id,
+            region,
+            hash,
+            sealed_at,
+            verify_on_deref: false,
+        }
+    }
+
+    /// verify the value every time it is accessed, and panic if it changed (default: false)
+    pub fn verify_on_deref(mut self, verify_on_deref: bool) -> Self {
+        self.verify_on_deref = verify_on_deref;
+        self
+    }
+
+    /// hash the value right now and compare it to the hash from when it was sealed. Returns the mismatch if the
+    /// value changed
+    pub fn verify(&self) -> Option<MemoryEvent> {
+        let current = (self.current_hash)(&self.value);
+        (current != self.hash).then(|| mismatch(&self.region, self.hash, current, self.sealed_at))
+    }
+
+    /// the label given when the value was sealed
+    pub fn label(&self) -> &str {
+        &self.region.source
+    }
+}
+
+impl<T: Send + Sync + 'static> Deref for Sealed<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        if self.verify_on_deref {
+            if let Some(error) = self.verify() {
+                panic!("Sealed value {} was modified: {:#?}", self.label(), error);
+            }
+        }
+        &self.value
+    }
+}
+
+impl<T: Send + Sync + 'static> Drop for Sealed<T> {
+    fn drop(&mut self) {
+        SEALED.lock().unwrap().values.remove(&self.id);
+    }
+}
+
+impl<T: Send + Sync + 'static + fmt::Debug> fmt::Debug for Sealed<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sealed")
+            .field("label", &self.label())
+            .field("value", &self.value)
+            .finish()
+    }
+}
+
+fn mismatch(region: &Region, old_hash: Hash, new_hash: Hash, sealed_at: Instant) -> MemoryEvent {
+    MemoryEvent {
+        region: region.clone(),
+        old_hash,
+        new_hash,
+        old_hash_computed_at: sealed_at,
+        changed_offsets: vec![],
+        byte_diffs: vec![],
+        flipped_bits: 0,
+        locations: vec![],
+        correctable: None,
+    }
+}
+
+/// hash all sealed values, returning the ones that changed
+pub(crate) fn check_all() -> Vec<MemoryEvent> {
+    // don't hold the lock while hashing, the values stay alive through their Arc
+    let entries: Vec<_> = SEALED
+        .lock()
+        .unwrap()
+        .values
+        .values()
+        .map(|entry| {
+            (
+                entry.region.clone(),
+                entry.hash,
+                entry.sealed_at,
+                entry.current_hash.clone(),
+            )
+        })
+        .collect();
+    entries
+        .into_iter()
+        .filter_map(|(region, hash, sealed_at, current_hash)| {
+            let current = current_hash();
+            (current != hash).then(|| mismatch(&region, hash, current, sealed_at))
+        })
+        .collect()
+}
+
+/// feeds [std::hash::Hash] implementations into the configured hash algorithm
+#[derive(Default)]
+struct HashWriter {
+    #[cfg(feature = "blake3")]
+    hasher: blake3::Hasher,
+    #[cfg(all(not(feature = "blake3"), feature = "crc64"))]
+    hasher: crc64fast::Digest,
+}
+
+impl HashWriter {
+    fn finalize(&self) -> Hash {
+        #[cfg(feature = "blake3")]
+        return Hash(self.hasher.finalize().into());
+        #[cfg(all(not(feature = "blake3"), feature = "crc64"))]
+        Hash(self.hasher.sum64())
+    }
+}
+
+impl std::hash::Hasher for HashWriter {
+    fn write(&mut self, bytes: &[u8]) {
+        #[cfg(feature = "blake3")]
+        self.hasher.update(bytes);
+        #[cfg(all(not(feature = "blake3"), feature = "crc64"))]
+        self.hasher.write(bytes);
+    }
+
+    fn finish(&self) -> u64 {
+        #[cfg(feature = "blake3")]
+        return u64::from_le_bytes(self.finalize().0[..8].try_into().unwrap());
+        #[cfg(all(not(feature = "blake3"), feature = "crc64"))]
+        self.finalize().0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU8, Ordering};
+
+    /// a value that can change behind the seal, to simulate memory corruption
+    #[derive(Default)]
+    struct Corruptible(AtomicU8);
+
+    impl std::hash::Hash for Corruptible {
+        fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+            self.0.load(Ordering::Relaxed).hash(state)
+        }
+    }
+
+    #[test]
+    fn detects_changed_values() {
+        let sealed = Sealed::new("corruptible", Corruptible::default());
+        assert!(sealed.verify().is_none());
+        assert!(check_all()
+            .iter()
+            .all(|event| event.region.source != "corruptible"));
+
+        sealed.0.store(1, Ordering::Relaxed);
+        let error = sealed.verify().unwrap();
+        assert_eq!(error.region.source, "corruptible");
+        assert_eq!(error.region.segment, Segment::Sealed);
+        assert!(check_all()
+            .iter()
+            .any(|event| event.region.source == "corruptible"));
+
+        drop(sealed);
+        assert!(check_all()
+            .iter()
+            .all(|event| event.region.source != "corruptible"));
+    }
+
+    #[test]
+    fn hashes_bytes_of_plain_data() {
+        let sealed = Sealed::new_plain("plain", [0.0f32, 1.5]);
+        assert!(sealed.verify().is_none());
+        assert_eq!(sealed.region.end - sealed.region.start, 8);
+        assert_eq!(sealed.region.start, &*sealed as *const _ as usize);
+        // equal floats with different bytes
+        assert_ne!(
+            hash_of_bytes(&[0.0f32, 1.5]),
+            hash_of_bytes(&[-0.0f32, 1.5])
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Sealed value checked on access was modified")]
+    fn panics_on_deref_after_change() {
+        let sealed = Sealed::new("checked on access", Corruptible::default()).verify_on_deref(true);
+        sealed.0.store(1, Ordering::Relaxed);
+        sealed.0.load(Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,265 @@
+use std::{collections::HashMap, time::Instant};
+
+use crate::{
+    error::Error, get_all_regions, handle::Control, CheckerConfig, Hash, MemoryEvent, Region,
+    RegionHash,
+};
+
+/// Hashes of all checked memory regions at one point in time
+///
+/// ```rust
+///   use process_consistency::Snapshot;
+///   let baseline = Snapshot::capture().unwrap();
+///   // ... e.g. right before signing a transaction
+///   let report = Snapshot::verify(&baseline).unwrap();
+///   assert!(report.is_intact(), "Memory Error: {:#?}", report.changed);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    /// discovery settings, reused when verifying against this snapshot
+    config: CheckerConfig,
+    pub(crate) regions: HashMap<Region, RegionHash>,
+}
+
+impl Snapshot {
+    /// hash all executable regions, using the default settings of [ProcessConsistencyChecker](crate::ProcessConsistencyChecker)
+    pub fn capture() -> Result<Self, Error> {
+        Self::capture_with(
+            &crate::ProcessConsistencyChecker::new().config,
+            vec![],
+            None,
+        )
+    }
+
+    pub(crate) fn new(config: &CheckerConfig, regions: HashMap<Region, RegionHash>) -> Self {
+        Self {
+            config: config.clone(),
+            regions,
+        }
+    }
+
+    /// hash all regions found with the given config, and `extra` regions that were registered by the user. With the
+    /// `control` of a running checker, modules that are being unloaded are skipped like the checker skips them
+    pub(crate) fn capture_with(
+        config: &CheckerConfig,
+        extra: Vec<Region>,
+        control: Option<&Control>,
+    ) -> Result<Self, Error> {
+        let now = Instant::now();
+        let mut regions = HashMap::new();
+        for region in get_all_regions(config)?.into_iter().chain(extra) {
+            let _in_use = match control.map(|control| control.use_module(&region)) {
+                Some(None) => continue,
+                in_use => in_use,
+            };
+            // skip regions that were unmapped since they were found
+            let Some(bytes) = (unsafe { region.read(config) })? else {
+                continue;
+            };
+            let mut hash = RegionHash::of_bytes(&bytes, config, now);
+            hash.keep_repair_data(&bytes, config);
+            drop(bytes);
+            regions.insert(region, hash);
+        }
+        Ok(Self {
+            config: config.clone(),
+            regions,
+        })
+    }
+
+    /// hash the current state of memory and compare it to this snapshot.
+    ///
+    /// Discovery uses the same settings that were used to create this snapshot
+    pub fn verify(&self) -> Result<VerifyReport, Error> {
+        self.verify_with(vec![], None)
+    }
+
+    /// like [verify](Self::verify), also hashing `extra` regions that were registered by the user, see
+    /// [capture_with](Self::capture_with)
+    pub(crate) fn verify_with(
+        &self,
+        extra: Vec<Region>,
+        control: Option<&Control>,
+    ) -> Result<VerifyReport, Error> {
+        let current = Self::capture_with(&self.config, extra, control)?;
+        let unchanged = current
+            .regions
+            .iter()
+            .filter(|(region, new)| {
+                self.regions
+                    .get(region)
+                    .is_some_and(|old| old.hash == new.hash)
+            })
+            .map(|(region, _)| region.clone())
+            .collect();
+        let diff = self.diff(&current);
+
+        Ok(VerifyReport {
+            unchanged,
+            changed: diff.changed,
+            added: diff.added,
+            removed: diff.removed,
+        })
+    }
+
+    /// compare this snapshot to a later one
+    ///
+    /// ```rust
+    ///   use process_consistency::Snapshot;
+    ///   let before = Snapshot::capture().unwrap();
+    ///   // ... e.g. dlopen a library
+    ///   let after = Snapshot::capture().unwrap();
+    ///   let diff = before.diff(&after);
+    ///   assert!(diff.changed.is_empty());
+    ///   println!("loaded: {:#?}", diff.added);
+    /// ```
+    pub fn diff(&self, later: &Snapshot) -> SnapshotDiff {
+        let mut diff = SnapshotDiff::default();
+        for (region, new) in &later.regions {
+            match self.regions.get(region) {
+                Some(old) if old.hash == new.hash => {}
+                Some(old) => {
+                    let new_bytes = new.golden.as_ref().map(|golden| golden.bytes());
+                    diff.changed
+                        .push(old.mismatch(region, new, &self.config, new_bytes.as_deref()))
+                }
+                None => diff.added.push(region.clone()),
+            }
+        }
+        diff.removed = self
+            .regions
+            .keys()
+            .filter(|region| !later.regions.contains_key(region))
+            .cloned()
+            .collect();
+        diff
+    }
+
+    /// all regions in this snapshot, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = SnapshotEntry> + '_ {
+        self.regions.iter().map(|(region, hash)| SnapshotEntry {
+            region: region.clone(),
+            hash: hash.hash,
+            computed_at: hash.computed_at,
+        })
+    }
+
+    /// number of regions in this snapshot
+    pub fn len(&self) -> usize {
+        self.regions.len()
+    }
+
+    /// true if this snapshot contains no regions
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+}
+
+/// A single region of a [Snapshot], see [Snapshot::iter]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SnapshotEntry {
+    /// the address, size, origin and file metadata of the region
+    pub region: Region,
+    /// the hash of the region
+    pub hash: Hash,
+    /// when hash was computed
+    pub computed_at: Instant,
+}
+
+/// Differences between two [Snapshot]s, see [Snapshot::diff]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct SnapshotDiff {
+    /// regions whose hash differs between the snapshots
+    pub changed: Vec<MemoryEvent>,
+    /// regions that only exist in the later snapshot
+    pub added: Vec<Region>,
+    /// regions that only exist in the earlier snapshot
+    pub removed: Vec<Region>,
+}
+
+impl SnapshotDiff {
+    /// true if both snapshots contain the same regions with the same hashes
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// Result of comparing the current memory to a [Snapshot], see [Snapshot::verify]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct VerifyReport {
+    /// regions whose hash matches the snapshot
+    pub unchanged: Vec<Region>,
+    /// regions whose hash differs from the snapshot
+    pub changed: Vec<MemoryEvent>,
+    /// regions that didn't exist when the snapshot was taken, e.g. because a library was loaded since then
+    pub added: Vec<Region>,
+    /// regions from the snapshot that don't exist anymore, e.g. because a library was unloaded since then
+    pub removed: Vec<Region>,
+}
+
+impl VerifyReport {
+    /// true if no region changed. Added or removed regions don't count as changes
+    pub fn is_intact(&self) -> bool {
+        self.changed.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unchanged_memory_verifies() {
+        let _lock = crate::tests::CODE_LOCK.lock().unwrap();
+        let baseline = Snapshot::capture().unwrap();
+        assert!(!baseline.is_empty());
+        let report = baseline.verify().unwrap();
+        assert!(report.is_intact());
+        assert!(!report.unchanged.is_empty());
+    }
+
+    #[test]
+    fn diff_finds_all_differences() {
+        let before = Snapshot::capture().unwrap();
+        assert!(before.diff(&before).is_empty());
+
+        let mut after = before.clone();
+        let mut regions = before.regions.keys();
+        let removed = regions.next().unwrap().clone();
+        let changed = regions.next().unwrap().clone();
+        after.regions.remove(&removed);
+        after.regions.get_mut(&changed).unwrap().hash = Hash(Default::default());
+
+        let diff = before.diff(&after);
+        assert_eq!(diff.removed, vec![removed.clone()]);
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].region, changed);
+        assert!(diff.added.is_empty());
+
+        let diff = after.diff(&before);
+        assert_eq!(diff.added, vec![removed]);
+        assert!(diff.removed.is_empty());
+    }
+
+    #[test]
+    fn diff_reports_changed_chunks() {
+        let before = crate::ProcessConsistencyChecker::new()
+            .chunk_size(Some(4096))
+            .capture_snapshot()
+            .unwrap();
+        let mut after = before.clone();
+        let (region, hash) = after
+            .regions
+            .iter_mut()
+            .find(|(_, hash)| hash.chunks.len() > 2)
+            .unwrap();
+        hash.chunks[2] = Hash(Default::default());
+        hash.hash = Hash::combine(&hash.chunks);
+        let region = region.clone();
+
+        let diff = before.diff(&after);
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].region, region);
+        assert_eq!(diff.changed[0].changed_offsets, vec![2 * 4096]);
+    }
+}
@@ -0,0 +1,76 @@
+use std::fmt;
+
+use crate::Region;
+
+/// The function and source line an offset in a [Region] belongs to, see
+/// [symbolize](crate::ProcessConsistencyChecker::symbolize)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Location {
+    /// offset from the start of the region
+    pub offset: usize,
+    /// demangled name of the nearest symbol at or before the offset
+    pub function: Option<String>,
+    /// distance from the start of function
+    pub function_offset: usize,
+    /// source file, from DWARF debug info
+    pub file: Option<String>,
+    /// line in the source file, from DWARF debug info
+    pub line: Option<u32>,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.function {
+            Some(function) => write!(f, "{}+{:#x}", function, self.function_offset)?,
+            None => write!(f, "{:#x}", self.offset)?,
+        }
+        match (&self.file, self.line) {
+            (Some(file), Some(line)) => write!(f, " at {}:{}", file, line),
+            (Some(file), None) => write!(f, " at {}", file),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// resolve offsets in a region to functions. Consecutive offsets in the same function are reported once
+pub(crate) fn symbolize(region: &Region, offsets: &[usize]) -> Vec<Location> {
+    if offsets.is_empty() {
+        return vec![];
+    }
+    #[cfg(unix)]
+    let mut locations = crate::linux::symbolize(region, offsets);
+    #[cfg(windows)]
+    let mut locations: Vec<Location> = {
+        let _ = region;
+        vec![]
+    };
+    locations.dedup_by(|later, earlier| {
+        later.function.is_some()
+            && later.function == earlier.function
+            && later.function_offset >= earlier.function_offset
+    });
+    locations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_function_and_line() {
+        let mut location = Location {
+            offset: 0x1234,
+            function: Some("my_crate::check".into()),
+            function_offset: 0x1a,
+            file: Some("src/lib.rs".into()),
+            line: Some(42),
+        };
+        assert_eq!(
+            location.to_string(),
+            "my_crate::check+0x1a at src/lib.rs:42"
+        );
+        location.function = None;
+        location.file = None;
+        assert_eq!(location.to_string(), "0x1234");
+    }
+}
@@ -1,18 +1,25 @@
 use windows::Win32::{
     Foundation::CloseHandle,
     System::{
-        Diagnostics::ToolHelp::{
-            CreateToolhelp32Snapshot, Module32FirstW, Module32NextW, MODULEENTRY32W,
-            TH32CS_SNAPMODULE,
+        Diagnostics::{
+            Debug::FlushInstructionCache,
+            ToolHelp::{
+                CreateToolhelp32Snapshot, Module32FirstW, Module32NextW, MODULEENTRY32W,
+                TH32CS_SNAPMODULE,
+            },
         },
         Memory::{
-            VirtualQuery, MEMORY_BASIC_INFORMATION, PAGE_EXECUTE_READ, PAGE_EXECUTE_READWRITE,
-            PAGE_EXECUTE_WRITECOPY,
+            VirtualProtect, VirtualQuery, MEMORY_BASIC_INFORMATION, PAGE_EXECUTE_READ,
+            PAGE_EXECUTE_READWRITE, PAGE_EXECUTE_WRITECOPY, PAGE_PROTECTION_FLAGS, PAGE_READONLY,
         },
+        Threading::GetCurrentProcess,
     },
 };
 
-use crate::error::{win_get_last_error, Error};
+use crate::{
+    error::{win_get_last_error, Error},
+    CheckerConfig, Segment,
+};
 
 #[derive(Debug, Hash)]
 pub struct Module {
@@ -24,13 +31,10 @@ pub struct Module {
     exe_path: String,
 }
 
-pub(crate) fn get_executable_regions(
-    skip_libs: bool,
-    include_writable_code: bool,
-) -> Result<Vec<crate::Region>, Error> {
+pub(crate) fn get_executable_regions(config: &CheckerConfig) -> Result<Vec<crate::Region>, Error> {
     let mut res = vec![];
-    for module in get_module_list(skip_libs)? {
-        res.extend(module.get_executable_regions(include_writable_code)?);
+    for module in get_module_list(config.skip_libs)? {
+        res.extend(module.get_executable_regions(config)?);
     }
     Ok(res)
 }
@@ -116,10 +120,10 @@ impl Module {
         Ok(())
     }
 
-    /// get all regions of this module that are executable
+    /// get all regions of this module that are executable, and read-only data if configured
     pub(crate) fn get_executable_regions(
         &self,
-        include_writable_code: bool,
+        config: &CheckerConfig,
     ) -> Result<Vec<crate::Region>, Error> {
         let mut regions = vec![];
 
@@ -138,15 +142,36 @@ impl Module {
             }
             let segment_end = unsafe { info.BaseAddress.add(info.RegionSize) as *const u8 };
             let segment_end = segment_end.min(module_end);
-            if info.Protect | PAGE_EXECUTE_READ == PAGE_EXECUTE_READ
+            let segment = if info.Protect | PAGE_EXECUTE_READ == PAGE_EXECUTE_READ
                 || info.Protect | PAGE_EXECUTE_WRITECOPY == PAGE_EXECUTE_WRITECOPY
                 || (info.Protect | PAGE_EXECUTE_READWRITE == PAGE_EXECUTE_READWRITE
-                    && include_writable_code)
+                    && config.include_writable_code)
             {
+                Some(Segment::Text)
+            } else if info.Protect == PAGE_READONLY {
+                Some(Segment::ReadOnlyData)
+            } else {
+                None
+            };
+            if let Some(segment) = segment.filter(|segment| config.checks_segment(*segment)) {
                 regions.push(crate::Region {
-                    start: pos,
-                    end: segment_end,
+                    start: pos as usize,
+                    end: segment_end as usize,
                     source: self.exe_path.clone(),
+                    permissions: crate::Permissions {
+                        read: true,
+                        write: info.Protect == PAGE_EXECUTE_READWRITE,
+                        execute: segment == Segment::Text,
+                        shared: false,
+                    },
+                    segment,
+                    file: Some(crate::MappedFile {
+                        offset: (pos as usize - self.base_addr as usize) as u64,
+                        device: (0, 0),
+                        inode: 0,
+                        module_base: self.base_addr as usize,
+                        build_id: None,
+                    }),
                 });
             }
             if segment_end >= module_end {
@@ -159,6 +184,40 @@ impl Module {
     }
 }
 
+/// overwrite memory that is mapped without write permission, e.g. to restore corrupted code
+///
+/// # SAFETY
+/// `start..start + bytes.len()` has to be mapped code. Any other thread executing the overwritten range at the same
+/// time might see partially written code
+pub(crate) unsafe fn write_code(
+    start: *const u8,
+    bytes: &[u8],
+    _permissions: crate::Permissions,
+) -> Result<(), Error> {
+    let mut original = PAGE_PROTECTION_FLAGS(0);
+    unsafe {
+        if !VirtualProtect(
+            start as *const _,
+            bytes.len(),
+            PAGE_EXECUTE_READWRITE,
+            &mut original,
+        )
+        .as_bool()
+        {
+            return Err(win_get_last_error("VirtualProtect"));
+        }
+        std::ptr::copy_nonoverlapping(bytes.as_ptr(), start as *mut u8, bytes.len());
+        let mut ignored = PAGE_PROTECTION_FLAGS(0);
+        let restored =
+            VirtualProtect(start as *const _, bytes.len(), original, &mut ignored).as_bool();
+        FlushInstructionCache(GetCurrentProcess(), start as *const _, bytes.len());
+        if !restored {
+            return Err(win_get_last_error("VirtualProtect"));
+        }
+    }
+    Ok(())
+}
+
 fn debug_print_vec(v: &[u32]) -> String {
     let list: Vec<_> = v.iter().map(|x| format!("{:02x}", x)).collect();
     list.join(" ")
@@ -170,12 +229,19 @@ mod tests {
 
     #[test]
     fn test_all_combinations() {
-        println!("{:#?}", get_executable_regions(false, false));
+        let regions = |skip_libs, include_writable_code| {
+            get_executable_regions(&CheckerConfig {
+                skip_libs,
+                include_writable_code,
+                ..Default::default()
+            })
+        };
+        println!("{:#?}", regions(false, false));
         println!("----");
-        println!("{:#?}", get_executable_regions(true, false));
-        assert!(get_executable_regions(false, false).unwrap().len() > 1);
-        assert!(get_executable_regions(false, true).unwrap().len() > 1);
-        assert!(get_executable_regions(true, false).unwrap().len() <= 1);
-        assert!(get_executable_regions(true, true).unwrap().len() <= 1);
+        println!("{:#?}", regions(true, false));
+        assert!(regions(false, false).unwrap().len() > 1);
+        assert!(regions(false, true).unwrap().len() > 1);
+        assert!(regions(true, false).unwrap().len() <= 1);
+        assert!(regions(true, true).unwrap().len() <= 1);
     }
 }